@@ -0,0 +1,275 @@
+use srt;
+use srt::{Format, Subtitle, SubtitleFile};
+use error::ParseError;
+
+// Event fields written when converting from another format
+const DEFAULT_HEADER: &[&str] = &[
+	"[Script Info]",
+	"ScriptType: v4.00+",
+	"",
+	"[V4+ Styles]",
+	"Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding",
+	"Style: Default,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1",
+	"",
+	"[Events]",
+	"Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text"
+];
+
+const DIALOGUE: &str = "Dialogue:";
+
+// Returns the field names of the Format line of the [Events] section
+fn event_format(header: &[String]) -> Vec<String> {
+	let mut in_events = false;
+	for line in header.iter() {
+		let line = line.trim();
+		if line.starts_with('[') {
+			in_events = line.eq_ignore_ascii_case("[Events]");
+		}
+		else if in_events && line.starts_with("Format:") {
+			return line["Format:".len()..].split(',')
+				.map(|field| field.trim().to_string())
+				.collect();
+		}
+	}
+	Vec::new()
+}
+
+// Value of an event field we know nothing about
+fn default_field(name: &str) -> &'static str {
+	match name {
+		"Layer" | "MarginL" | "MarginR" | "MarginV" => "0",
+		"Style" => "Default",
+		"Marked" => "Marked=0",
+		_ => ""
+	}
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Parses an ASS or SSA file. Everything up to the Format line of the
+// [Events] section is kept verbatim as the header. Dialogue events become
+// subtitles, other event lines are kept verbatim as notes.
+//
+// The fields of a Dialogue event, except Start, End and Text, are stored in
+// the subtitle settings, separated by commas. The text is split in lines
+// on \N.
+pub fn parse_ass(content: &str) -> Result<SubtitleFile,ParseError> {
+	let mut file = SubtitleFile::new(Format::Ass);
+
+	let mut fields: Vec<String> = Vec::new();
+	let mut notes: Vec<String> = Vec::new();
+	let mut num = 1;
+
+	for (line_index, line_ori) in content.lines().enumerate() {
+		let line_num = line_index + 1;
+		let line = line_ori.trim_end_matches('\r');
+
+		if fields.is_empty() {
+			// Still in the header
+			file.header.push(line.to_string());
+			fields = event_format(&file.header);
+			if !fields.is_empty() {
+				let has_field = |name: &str| fields.iter().any(|field| field == name);
+				if !has_field("Start") || !has_field("End")
+				 || fields.last().map(|field| field.as_str()) != Some("Text") {
+					return Err(ParseError::BadEventFormat { line_num, text: line.to_string() });
+				}
+			}
+			continue;
+		}
+
+		if !line.starts_with(DIALOGUE) {
+			notes.push(line.to_string());
+			continue;
+		}
+
+		let values: Vec<&str> = line[DIALOGUE.len()..].splitn(fields.len(), ',').collect();
+		if values.len() != fields.len() {
+			return Err(ParseError::MissingEventFields { line_num, text: line.to_string() });
+		}
+
+		let mut subtitle = Subtitle { num, line_num: line_num as u32, ..Default::default() };
+		let mut settings: Vec<&str> = Vec::new();
+		for (field, value) in fields.iter().zip(values.iter()) {
+			match field.as_str() {
+				"Start" => {
+					subtitle.time_from = match srt::parse_srt_time(value.trim()) {
+						Some(milli) => milli,
+						None => {
+							return Err(ParseError::BadTimeFrom { line_num, text: value.to_string() });
+						}
+					};
+				},
+				"End" => {
+					subtitle.time_to = match srt::parse_srt_time(value.trim()) {
+						Some(milli) => milli,
+						None => {
+							return Err(ParseError::BadTimeTo { line_num, text: value.to_string() });
+						}
+					};
+				},
+				"Text" => {
+					for text in value.split("\\N") {
+						subtitle.push_text(text);
+					}
+				},
+				_ => settings.push(value)
+			}
+		}
+		subtitle.settings = settings.join(",");
+		subtitle.notes = notes;
+		notes = Vec::new();
+		file.subtitles.push(subtitle);
+		num += 1;
+	}
+
+	if fields.is_empty() {
+		return Err(ParseError::MissingEventFormat);
+	}
+	file.notes = notes;
+	Ok(file)
+}
+
+#[test]
+fn test_parse_ass() {
+	let ass = "[Script Info]\n\
+Title: test\n\
+\n\
+[V4+ Styles]\n\
+Format: Name, Fontname, Fontsize\n\
+Style: Default,Arial,20\n\
+\n\
+[Events]\n\
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+Comment: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,a comment\n\
+Dialogue: 0,0:00:01.50,0:00:03.07,Default,Bob,0,0,0,,{\\i1}Hello, you{\\i0}\\Nsecond line\n\
+Dialogue: 1,1:02:03.00,1:02:04.00,Top,,10,10,10,,Bye\n";
+	let file_res = parse_ass(ass);
+	assert!(file_res.is_ok(), "{}", file_res.err().unwrap());
+	let file = file_res.unwrap();
+	assert_eq!(file.header.len(), 9);
+	assert_eq!(file.subtitles.len(), 2);
+
+	let sub = &file.subtitles[0];
+	assert_eq!(sub.time_from, 1_500);
+	assert_eq!(sub.time_to, 3_070);
+	assert_eq!(sub.settings, " 0,Default,Bob,0,0,0,");
+	assert_eq!(sub.texts.len(), 2);
+	assert_eq!(sub.texts[0], "{\\i1}Hello, you{\\i0}");
+	assert_eq!(sub.texts[1], "second line");
+	assert_eq!(sub.notes, vec!["Comment: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,a comment"]);
+
+	let sub = &file.subtitles[1];
+	assert_eq!(sub.time_from, 3_723_000);
+	assert_eq!(sub.settings, " 1,Top,,10,10,10,");
+
+	assert!(parse_ass("[Script Info]\nTitle: test\n").is_err());
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Formats a time as H:MM:SS.cc, ASS has a centisecond precision
+pub fn format_ass_time_from_milli(milli: i32) -> String {
+	let milli = if milli < 0 { 0 } else { milli };
+	let centi = (milli + 5) / 10;
+
+	let centi_rem = centi % 100;
+	let mut tmp = centi / 100;
+	let hours = tmp / (60 * 60);
+	tmp %= 60 * 60;
+	let minutes = tmp / 60;
+	let seconds = tmp % 60;
+
+	format!("{}:{:02}:{:02}.{:02}", hours, minutes, seconds, centi_rem)
+}
+
+#[test]
+fn test_format_ass_time_from_milli() {
+	assert_eq!(format_ass_time_from_milli(1_500), "0:00:01.50");
+	assert_eq!(format_ass_time_from_milli(3_723_004), "1:02:03.00");
+	assert_eq!(format_ass_time_from_milli(59_995), "0:01:00.00");
+	assert_eq!(format_ass_time_from_milli(-10), "0:00:00.00");
+}
+
+///////////////////////////////////////////////////////////////////////////////
+pub fn format_ass(file: &SubtitleFile) -> String {
+	let mut ret = String::new();
+
+	let header: Vec<String> = if file.header.is_empty() {
+		// Converted from another format
+		DEFAULT_HEADER.iter().map(|line| line.to_string()).collect()
+	}
+	else {
+		file.header.clone()
+	};
+	for line in header.iter() {
+		ret.push_str(line);
+		ret.push_str("\r\n");
+	}
+	let fields = event_format(&header);
+
+	for subtitle in file.subtitles.iter() {
+		for note in subtitle.notes.iter() {
+			ret.push_str(note);
+			ret.push_str("\r\n");
+		}
+
+		// Settings from another format cannot be used
+		let setting_count = fields.len() - 3;
+		let settings: Vec<&str> = subtitle.settings.split(',').collect();
+		let settings_ok = settings.len() == setting_count;
+
+		let mut values: Vec<String> = Vec::new();
+		let mut setting_index = 0;
+		for field in fields.iter() {
+			let value = match field.as_str() {
+				"Start" => format_ass_time_from_milli(subtitle.time_from),
+				"End" => format_ass_time_from_milli(subtitle.time_to),
+				"Text" => subtitle.texts.join("\\N"),
+				_ => {
+					let value = if settings_ok {
+						settings[setting_index].to_string()
+					}
+					else if setting_index == 0 {
+						format!(" {}", default_field(field))
+					}
+					else {
+						default_field(field).to_string()
+					};
+					setting_index += 1;
+					value
+				}
+			};
+			values.push(value);
+		}
+		ret.push_str(DIALOGUE);
+		ret.push_str(&values.join(","));
+		ret.push_str("\r\n");
+	}
+
+	for note in file.notes.iter() {
+		ret.push_str(note);
+		ret.push_str("\r\n");
+	}
+	ret
+}
+
+#[test]
+fn test_format_ass() {
+	let ass = "[Script Info]\r\n\
+ScriptType: v4.00+\r\n\
+\r\n\
+[Events]\r\n\
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\r\n\
+Dialogue: 0,0:00:01.50,0:00:03.07,Default,Bob,0,0,0,,{\\i1}Hello, you{\\i0}\\Nsecond line\r\n\
+Comment: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,a comment\r\n\
+Dialogue:1,1:02:03.00,1:02:04.00,Top,,10,10,10,,Bye\r\n\
+\r\n\
+[Fonts]\r\n";
+	let file = parse_ass(ass).unwrap();
+	assert_eq!(format_ass(&file), ass);
+
+	// From SRT
+	let mut file = srt::parse_subtitles("1\n00:00:01,000 --> 00:00:02,000\nhi\n", Format::Srt, None).unwrap();
+	file.format = Format::Ass;
+	let converted = format_ass(&file);
+	assert!(converted.ends_with("\r\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,hi\r\n"));
+}
@@ -0,0 +1,563 @@
+use std;
+use std::io::prelude::*;
+use std::fs::File;
+use std::str;
+
+use error::ReadError;
+
+// Character encodings we can read and write
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+	Utf8,
+	Utf16Le,
+	Utf16Be,
+	Windows1252,
+	Iso8859_15,
+	Windows1250,
+	Windows1251
+}
+
+impl Encoding {
+	pub fn from_name(name: &str) -> Option<Encoding> {
+		match name.to_lowercase().as_str() {
+			"utf-8" | "utf8" => Some(Encoding::Utf8),
+			"utf-16le" | "utf16le" => Some(Encoding::Utf16Le),
+			"utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+			"windows-1252" | "cp1252" | "latin1" => Some(Encoding::Windows1252),
+			"iso-8859-15" | "latin9" => Some(Encoding::Iso8859_15),
+			"windows-1250" | "cp1250" => Some(Encoding::Windows1250),
+			"windows-1251" | "cp1251" => Some(Encoding::Windows1251),
+			_ => None
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		match *self {
+			Encoding::Utf8 => "utf-8",
+			Encoding::Utf16Le => "utf-16le",
+			Encoding::Utf16Be => "utf-16be",
+			Encoding::Windows1252 => "windows-1252",
+			Encoding::Iso8859_15 => "iso-8859-15",
+			Encoding::Windows1250 => "windows-1250",
+			Encoding::Windows1251 => "windows-1251"
+		}
+	}
+
+	// Byte order mark
+	fn bom(&self) -> &'static [u8] {
+		match *self {
+			Encoding::Utf8 => &BOM,
+			Encoding::Utf16Le => &[0xFF, 0xFE],
+			Encoding::Utf16Be => &[0xFE, 0xFF],
+			_ => &[]
+		}
+	}
+}
+
+// Line endings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Eol {
+	Lf,
+	Crlf
+}
+
+impl Eol {
+	pub fn from_name(name: &str) -> Option<Eol> {
+		match name.to_lowercase().as_str() {
+			"lf" => Some(Eol::Lf),
+			"crlf" => Some(Eol::Crlf),
+			_ => None
+		}
+	}
+}
+
+// How the text of a file is stored, so it can be written back the same way
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextFormat {
+	pub encoding: Encoding,
+	// Only for UTF-8, UTF-16 is always written with one
+	pub bom: bool,
+	pub eol: Eol
+}
+
+impl Default for TextFormat {
+	// What we write when we know nothing about the input
+	fn default() -> TextFormat {
+		TextFormat { encoding: Encoding::Utf8, bom: true, eol: Eol::Crlf }
+	}
+}
+
+// The text of a file, how it was stored, and why we think so
+pub struct Decoded {
+	pub text: String,
+	pub text_format: TextFormat,
+	pub reason: &'static str
+}
+
+///////////////////////////////////////////////////////////////////////////////
+const W1252_80_9F: [char; 32] = [
+	'\u{20ac}', '\u{0081}', '\u{201a}', '\u{0192}',
+	'\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+	'\u{02c6}', '\u{2030}', '\u{0160}', '\u{2039}',
+	'\u{0152}', '\u{008d}', '\u{017d}', '\u{008f}',
+	'\u{0090}', '\u{2018}', '\u{2019}', '\u{201c}',
+	'\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+	'\u{02dc}', '\u{2122}', '\u{0161}', '\u{203a}',
+	'\u{0153}', '\u{009d}', '\u{017e}', '\u{0178}'
+	];
+
+const W1250_80_FF: [char; 128] = [
+	'\u{20ac}', '\u{0081}', '\u{201a}', '\u{0083}',
+	'\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+	'\u{0088}', '\u{2030}', '\u{0160}', '\u{2039}',
+	'\u{015a}', '\u{0164}', '\u{017d}', '\u{0179}',
+	'\u{0090}', '\u{2018}', '\u{2019}', '\u{201c}',
+	'\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+	'\u{0098}', '\u{2122}', '\u{0161}', '\u{203a}',
+	'\u{015b}', '\u{0165}', '\u{017e}', '\u{017a}',
+	'\u{00a0}', '\u{02c7}', '\u{02d8}', '\u{0141}',
+	'\u{00a4}', '\u{0104}', '\u{00a6}', '\u{00a7}',
+	'\u{00a8}', '\u{00a9}', '\u{015e}', '\u{00ab}',
+	'\u{00ac}', '\u{00ad}', '\u{00ae}', '\u{017b}',
+	'\u{00b0}', '\u{00b1}', '\u{02db}', '\u{0142}',
+	'\u{00b4}', '\u{00b5}', '\u{00b6}', '\u{00b7}',
+	'\u{00b8}', '\u{0105}', '\u{015f}', '\u{00bb}',
+	'\u{013d}', '\u{02dd}', '\u{013e}', '\u{017c}',
+	'\u{0154}', '\u{00c1}', '\u{00c2}', '\u{0102}',
+	'\u{00c4}', '\u{0139}', '\u{0106}', '\u{00c7}',
+	'\u{010c}', '\u{00c9}', '\u{0118}', '\u{00cb}',
+	'\u{011a}', '\u{00cd}', '\u{00ce}', '\u{010e}',
+	'\u{0110}', '\u{0143}', '\u{0147}', '\u{00d3}',
+	'\u{00d4}', '\u{0150}', '\u{00d6}', '\u{00d7}',
+	'\u{0158}', '\u{016e}', '\u{00da}', '\u{0170}',
+	'\u{00dc}', '\u{00dd}', '\u{0162}', '\u{00df}',
+	'\u{0155}', '\u{00e1}', '\u{00e2}', '\u{0103}',
+	'\u{00e4}', '\u{013a}', '\u{0107}', '\u{00e7}',
+	'\u{010d}', '\u{00e9}', '\u{0119}', '\u{00eb}',
+	'\u{011b}', '\u{00ed}', '\u{00ee}', '\u{010f}',
+	'\u{0111}', '\u{0144}', '\u{0148}', '\u{00f3}',
+	'\u{00f4}', '\u{0151}', '\u{00f6}', '\u{00f7}',
+	'\u{0159}', '\u{016f}', '\u{00fa}', '\u{0171}',
+	'\u{00fc}', '\u{00fd}', '\u{0163}', '\u{02d9}'
+	];
+
+const W1251_80_FF: [char; 128] = [
+	'\u{0402}', '\u{0403}', '\u{201a}', '\u{0453}',
+	'\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+	'\u{20ac}', '\u{2030}', '\u{0409}', '\u{2039}',
+	'\u{040a}', '\u{040c}', '\u{040b}', '\u{040f}',
+	'\u{0452}', '\u{2018}', '\u{2019}', '\u{201c}',
+	'\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+	'\u{0098}', '\u{2122}', '\u{0459}', '\u{203a}',
+	'\u{045a}', '\u{045c}', '\u{045b}', '\u{045f}',
+	'\u{00a0}', '\u{040e}', '\u{045e}', '\u{0408}',
+	'\u{00a4}', '\u{0490}', '\u{00a6}', '\u{00a7}',
+	'\u{0401}', '\u{00a9}', '\u{0404}', '\u{00ab}',
+	'\u{00ac}', '\u{00ad}', '\u{00ae}', '\u{0407}',
+	'\u{00b0}', '\u{00b1}', '\u{0406}', '\u{0456}',
+	'\u{0491}', '\u{00b5}', '\u{00b6}', '\u{00b7}',
+	'\u{0451}', '\u{2116}', '\u{0454}', '\u{00bb}',
+	'\u{0458}', '\u{0405}', '\u{0455}', '\u{0457}',
+	'\u{0410}', '\u{0411}', '\u{0412}', '\u{0413}',
+	'\u{0414}', '\u{0415}', '\u{0416}', '\u{0417}',
+	'\u{0418}', '\u{0419}', '\u{041a}', '\u{041b}',
+	'\u{041c}', '\u{041d}', '\u{041e}', '\u{041f}',
+	'\u{0420}', '\u{0421}', '\u{0422}', '\u{0423}',
+	'\u{0424}', '\u{0425}', '\u{0426}', '\u{0427}',
+	'\u{0428}', '\u{0429}', '\u{042a}', '\u{042b}',
+	'\u{042c}', '\u{042d}', '\u{042e}', '\u{042f}',
+	'\u{0430}', '\u{0431}', '\u{0432}', '\u{0433}',
+	'\u{0434}', '\u{0435}', '\u{0436}', '\u{0437}',
+	'\u{0438}', '\u{0439}', '\u{043a}', '\u{043b}',
+	'\u{043c}', '\u{043d}', '\u{043e}', '\u{043f}',
+	'\u{0440}', '\u{0441}', '\u{0442}', '\u{0443}',
+	'\u{0444}', '\u{0445}', '\u{0446}', '\u{0447}',
+	'\u{0448}', '\u{0449}', '\u{044a}', '\u{044b}',
+	'\u{044c}', '\u{044d}', '\u{044e}', '\u{044f}'
+	];
+
+// Single byte code pages, by order of preference when the statistics
+// cannot tell
+const CODE_PAGES: [Encoding; 4] = [
+	Encoding::Windows1252,
+	Encoding::Iso8859_15,
+	Encoding::Windows1250,
+	Encoding::Windows1251
+];
+
+// Character of a byte in a single byte code page
+fn decode_byte(cp8: u8, encoding: Encoding) -> char {
+	if cp8 < 0x80 {
+		return cp8 as char;
+	}
+	match encoding {
+		Encoding::Windows1252 => if cp8 <= 0x9f {
+			W1252_80_9F[(cp8 as usize) - 0x80]
+		}
+		else {
+			cp8 as char
+		},
+		// Latin-1 with the euro sign and some french and finnish letters
+		Encoding::Iso8859_15 => match cp8 {
+			0xa4 => '€',
+			0xa6 => 'Š',
+			0xa8 => 'š',
+			0xb4 => 'Ž',
+			0xb8 => 'ž',
+			0xbc => 'Œ',
+			0xbd => 'œ',
+			0xbe => 'Ÿ',
+			_ => cp8 as char
+		},
+		Encoding::Windows1250 => W1250_80_FF[(cp8 as usize) - 0x80],
+		Encoding::Windows1251 => W1251_80_FF[(cp8 as usize) - 0x80],
+		Encoding::Utf8 | Encoding::Utf16Le | Encoding::Utf16Be => cp8 as char
+	}
+}
+
+fn decode_single_byte(content: &[u8], encoding: Encoding) -> String {
+	let mut ret = String::with_capacity(content.len() * 2);
+	for cp8 in content {
+		ret.push(decode_byte(*cp8, encoding));
+	}
+	ret
+}
+
+#[test]
+fn test_decode_windows_1252() {
+    let raw = [
+    	0x64u8, 0xe9, 0x6a, 0xe0,
+    	0x20,
+    	0x62,0x9c,0x75,0x66,
+    	0x20,
+    	0x33,0x80];
+    let text = decode_single_byte(&raw, Encoding::Windows1252);
+    assert!(text == "déjà bœuf 3€");
+}
+
+// Byte of a character in a single byte code page, if any
+fn encode_char(c: char, encoding: Encoding) -> Option<u8> {
+	if c.is_ascii() {
+		return Some(c as u8);
+	}
+	(0x80..=0xffu8).find(|&cp8| decode_byte(cp8, encoding) == c)
+}
+
+fn decode_utf16(content: &[u8], encoding: Encoding) -> Result<String,ReadError> {
+	if !content.len().is_multiple_of(2) {
+		return Err(ReadError::OddUtf16Length);
+	}
+	let units: Vec<u16> = content.chunks(2).map(|pair| match encoding {
+		Encoding::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+		_ => u16::from_le_bytes([pair[0], pair[1]])
+	}).collect();
+	String::from_utf16(&units).map_err(ReadError::InvalidUtf16)
+}
+
+// Letters frequent in the languages written with each code page, and letters
+// that are less frequent but still expected. ù, œ and ï have the bytes of
+// frequent Windows-1250 letters (ů, ś, ď), so they weigh as much.
+const FREQUENT_WESTERN: &str = "éèàçêáíóúñüöäãõßùœï";
+const USUAL_WESTERN: &str = "âîôûëìòæ";
+const FREQUENT_CENTRAL: &str = "áéíóúýčďěňřšťůžłąęśćźöüäő";
+const USUAL_CENTRAL: &str = "ńżôľĺŕăâîşţűđ";
+const USUAL_CYRILLIC: &str = "ёіїєґўђјљњћџѓќѕ";
+
+fn is_cyrillic(c: char) -> bool {
+	('\u{400}'..='\u{4ff}').contains(&c)
+}
+
+// How much a text decoded with a code page looks like real text
+fn code_page_score(content: &[u8], encoding: Encoding) -> i32 {
+	let (frequent, usual) = match encoding {
+		Encoding::Windows1250 => (FREQUENT_CENTRAL, USUAL_CENTRAL),
+		Encoding::Windows1251 => ("", USUAL_CYRILLIC),
+		_ => (FREQUENT_WESTERN, USUAL_WESTERN)
+	};
+	let mut score = 0;
+	let mut previous = ' ';
+	for c in decode_single_byte(content, encoding).chars() {
+		if ('\u{80}'..='\u{9f}').contains(&c) {
+			// Control characters
+			score -= 10;
+		}
+		else if c.is_alphabetic() && !c.is_ascii() {
+			let lower = c.to_lowercase().next().unwrap_or(c);
+			score += if frequent.contains(lower) || (encoding == Encoding::Windows1251 && ('а'..='я').contains(&lower)) {
+				3
+			}
+			else if usual.contains(lower) {
+				1
+			}
+			else {
+				-1
+			};
+		}
+		else if !c.is_ascii() && previous.is_alphabetic() && !"\u{a0}’‘”…–—".contains(c) {
+			// Symbols like ³ or ¿ stuck to a word
+			score -= 2;
+		}
+		// Like "ŻQué", for "¿Qué"
+		if previous.is_lowercase() && c.is_uppercase() {
+			score -= 3;
+		}
+		// Latin and cyrillic letters in the same word
+		if previous.is_alphabetic() && c.is_alphabetic() && is_cyrillic(previous) != is_cyrillic(c) {
+			score -= 5;
+		}
+		previous = c;
+	}
+	score
+}
+
+// Guesses the code page of a text that is not UTF-8
+fn detect_code_page(content: &[u8]) -> Encoding {
+	let mut best = (CODE_PAGES[0], code_page_score(content, CODE_PAGES[0]));
+	for &encoding in CODE_PAGES[1..].iter() {
+		let score = code_page_score(content, encoding);
+		if score > best.1 {
+			best = (encoding, score);
+		}
+	}
+	best.0
+}
+
+#[test]
+fn test_detect_code_page() {
+	let detect = |text: &str, encoding: Encoding| -> Encoding {
+		let content: Vec<u8> = text.chars().map(|c| encode_char(c, encoding).unwrap()).collect();
+		detect_code_page(&content)
+	};
+	let texts = [
+		("Déjà l'été ? Ça va très bien, merci.", Encoding::Windows1252),
+		("Où est ton cœur ? Là où il est.", Encoding::Windows1252),
+		("Bœuf, œuf, sœur, cœur.", Encoding::Windows1252),
+		("Naïve ambiguïté où.", Encoding::Windows1252),
+		("Le cœur a ses raisons, 10 €.", Encoding::Iso8859_15),
+		("¿Qué pasó? Mañana vamos a la montaña.", Encoding::Windows1252),
+		("Größere Übungen für die Schüler.", Encoding::Windows1252),
+		("Příliš žluťoučký kůň úpěl ďábelské ódy.", Encoding::Windows1250),
+		("Zażółć gęślą jaźń, ładna łódź.", Encoding::Windows1250),
+		("Ďakujem, dnes už nikam nepůjdu, stačí.", Encoding::Windows1250),
+		("Привет, как дела? Всё хорошо, спасибо.", Encoding::Windows1251)
+	];
+	for &(text, encoding) in texts.iter() {
+		assert_eq!(detect(text, encoding), encoding, "{}", text);
+	}
+}
+
+// Bytes of a text in Windows-1252, if it can be written so. With lost_nbsp,
+// the space after 'Ã' is taken for the no-break space of "Ã\u{a0}" ("à").
+fn windows_1252_bytes(text: &str, lost_nbsp: bool) -> Option<Vec<u8>> {
+	let mut bytes: Vec<u8> = Vec::with_capacity(text.len());
+	for c in text.chars() {
+		match encode_char(c, Encoding::Windows1252) {
+			Some(b' ') if lost_nbsp && bytes.last() == Some(&0xc3) => bytes.push(0xa0),
+			Some(byte) => bytes.push(byte),
+			None => return None
+		}
+	}
+	Some(bytes)
+}
+
+// Decodes once UTF-8 text decoded as Windows-1252
+fn undo_once(text: &str) -> Option<String> {
+	let decode = |lost_nbsp| windows_1252_bytes(text, lost_nbsp).and_then(|bytes| String::from_utf8(bytes).ok());
+	if let Some(repaired) = decode(false) {
+		return Some(repaired);
+	}
+	// "Ã " is also a real capital letter followed by a space, as in "IRMÃ DELE":
+	// the lost no-break space is trusted only when the rest is mis-decoded too
+	let rest = text.replace("Ã ", " ");
+	match windows_1252_bytes(&rest, false).and_then(|bytes| String::from_utf8(bytes).ok()) {
+		Some(ref repaired_rest) if *repaired_rest != rest => decode(true),
+		_ => None
+	}
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Undoes UTF-8 text decoded as Windows-1252, once or twice: "Ã©" or "ÃƒÂ©"
+// gives "é". Returns None if the text does not look like that.
+pub fn undo_mojibake(text: &str) -> Option<String> {
+	let mut current = text.to_string();
+	for _ in 0..2 {
+		match undo_once(&current) {
+			Some(repaired) if repaired != current => current = repaired,
+			_ => break
+		}
+	}
+	if current != text {
+		Some(current)
+	}
+	else {
+		None
+	}
+}
+
+#[test]
+fn test_undo_mojibake() {
+	assert_eq!(undo_mojibake("Ã‡a va, l'Ã©tÃ©"), Some("Ça va, l'été".to_string()));
+	assert_eq!(undo_mojibake("Câ€™est dÃ©jÃ  fini"), Some("C’est déjà fini".to_string()));
+	assert_eq!(undo_mojibake("Un cÅ“ur Ã  prendre"), Some("Un cœur à prendre".to_string()));
+	assert_eq!(undo_mojibake("l'ÃƒÂ©tÃƒÂ©"), Some("l'été".to_string()));
+	assert_eq!(undo_mojibake("déjà l'été"), None);
+	assert_eq!(undo_mojibake("Hello"), None);
+	assert_eq!(undo_mojibake("Ã©tÃ© 2ᵉ"), None);
+	assert_eq!(undo_mojibake("Ã©tÃ© â€” 1áµ‰"), Some("été — 1ᵉ".to_string()));
+	assert_eq!(undo_mojibake("A IRMÃ DELE CHEGOU"), None);
+	assert_eq!(undo_mojibake("A IRMÃ, O IRMÃO"), None);
+	assert_eq!(undo_mojibake("SÃO PAULO, MANHÃ"), None);
+}
+
+const BOM: [u8;3] = [0xEF, 0xBB, 0xBF];
+
+///////////////////////////////////////////////////////////////////////////////
+// Decodes the content of a text file, and tells how it was stored. Without
+// a byte order mark, UTF-8 is tried first, then the most likely single byte
+// code page. A given encoding skips the detection.
+pub fn decode(content: &[u8], forced: Option<Encoding>) -> Result<Decoded,ReadError> {
+	let with_bom = [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be].iter()
+		.find(|encoding| content.starts_with(encoding.bom()))
+		.cloned();
+
+	let (encoding, reason) = match (forced, with_bom) {
+		(Some(encoding), _) => (encoding, "as given"),
+		(None, Some(encoding)) => (encoding, "byte order mark"),
+		(None, None) => if str::from_utf8(content).is_ok() {
+			(Encoding::Utf8, "valid UTF-8")
+		}
+		else {
+			(detect_code_page(content), "character statistics")
+		}
+	};
+	let bom = with_bom == Some(encoding);
+	let content = if bom {
+		&content[encoding.bom().len()..]
+	}
+	else {
+		content
+	};
+
+	let content_str = match encoding {
+		Encoding::Utf8 => match str::from_utf8(content) {
+			Ok(res) => res.to_string(),
+			Err(err) => {
+				return Err(ReadError::InvalidUtf8(err));
+			}
+		},
+		Encoding::Utf16Le | Encoding::Utf16Be => decode_utf16(content, encoding)?,
+		_ => decode_single_byte(content, encoding)
+	};
+
+	// The first line ending tells, CRLF if there is none
+	let eol = match content_str.find('\n') {
+		Some(index) if index == 0 || content_str.as_bytes()[index - 1] != b'\r' => Eol::Lf,
+		_ => Eol::Crlf
+	};
+	Ok(Decoded {
+		text: content_str,
+		text_format: TextFormat { encoding, bom, eol },
+		reason
+	})
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Encodes a text, written with CRLF line endings, the way text_format tells.
+// Returns the bytes and the number of characters the encoding cannot
+// represent, replaced by '?'.
+pub fn encode(text: &str, text_format: &TextFormat) -> (Vec<u8>, usize) {
+	let text = match text_format.eol {
+		Eol::Crlf => std::borrow::Cow::Borrowed(text),
+		Eol::Lf => std::borrow::Cow::Owned(text.replace("\r\n", "\n"))
+	};
+	let encoding = text_format.encoding;
+	let mut ret = Vec::with_capacity(text.len() + 3);
+	let mut unmappable = 0;
+	match encoding {
+		Encoding::Utf8 => {
+			if text_format.bom {
+				ret.extend_from_slice(&BOM);
+			}
+			ret.extend_from_slice(text.as_bytes());
+		},
+		Encoding::Utf16Le | Encoding::Utf16Be => {
+			ret.extend_from_slice(encoding.bom());
+			for unit in text.encode_utf16() {
+				if encoding == Encoding::Utf16Le {
+					ret.extend_from_slice(&unit.to_le_bytes());
+				}
+				else {
+					ret.extend_from_slice(&unit.to_be_bytes());
+				}
+			}
+		},
+		_ => for c in text.chars() {
+			match encode_char(c, encoding) {
+				Some(byte) => ret.push(byte),
+				None => {
+					ret.push(b'?');
+					unmappable += 1;
+				}
+			}
+		}
+	}
+	(ret, unmappable)
+}
+
+#[test]
+fn test_decode_encode() {
+	let decoded = decode(b"1\nabc\n", None).unwrap();
+	assert_eq!(decoded.text, "1\nabc\n");
+	assert_eq!(decoded.text_format, TextFormat { encoding: Encoding::Utf8, bom: false, eol: Eol::Lf });
+	assert_eq!(decoded.reason, "valid UTF-8");
+	assert_eq!(encode("1\r\nabc\r\n", &decoded.text_format), (b"1\nabc\n".to_vec(), 0));
+
+	let decoded = decode(b"\xEF\xBB\xBF1\r\n", None).unwrap();
+	assert_eq!(decoded.text, "1\r\n");
+	assert_eq!(decoded.text_format, TextFormat::default());
+	assert_eq!(encode("1\r\n", &decoded.text_format).0, b"\xEF\xBB\xBF1\r\n".to_vec());
+
+	let decoded = decode(b"d\xe9j\xe0 \x80\r\n", None).unwrap();
+	assert_eq!(decoded.text, "déjà €\r\n");
+	assert_eq!(decoded.text_format.encoding, Encoding::Windows1252);
+	assert_eq!(decoded.text_format.eol, Eol::Crlf);
+	assert_eq!(decoded.reason, "character statistics");
+	assert_eq!(encode("déjà €ᵉ\r\n", &decoded.text_format), (b"d\xe9j\xe0 \x80?\r\n".to_vec(), 1));
+
+	let utf16 = b"\xFF\xFEa\x00\xE9\x00\n\x00";
+	let decoded = decode(utf16, None).unwrap();
+	assert_eq!(decoded.text, "aé\n");
+	assert_eq!(decoded.text_format.encoding, Encoding::Utf16Le);
+	assert_eq!(decoded.reason, "byte order mark");
+	assert_eq!(encode("aé\r\n", &decoded.text_format).0, utf16.to_vec());
+	assert_eq!(decode(b"\xFE\xFF\x00a", None).unwrap().text, "a");
+
+	let decoded = decode(b"\xcf\xf0\xe8", Some(Encoding::Windows1251)).unwrap();
+	assert_eq!(decoded.text, "При");
+	assert_eq!(decoded.reason, "as given");
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Reads a text file and decodes it, with the given encoding if any
+pub fn read_text_file_format(file_path: &str, forced: Option<Encoding>) -> Result<Decoded,ReadError> {
+	let content = {
+		let mut file = match File::open(file_path) {
+			Ok(file) => file,
+			Err(err) => {
+				return Err(ReadError::Open(err));
+			}
+		};
+		let mut bytes = Vec::new();
+		if let Err(err) = file.read_to_end(&mut bytes) {
+			return Err(ReadError::Read(err));
+		};
+		bytes
+	};
+	decode(&content, forced)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Reads a text file and decodes it to a String
+pub fn read_text_file(file_path: &str) -> Result<String,ReadError> {
+	read_text_file_format(file_path, None).map(|decoded| decoded.text)
+}
@@ -0,0 +1,195 @@
+use std::fmt;
+
+use srt::SubtitleFile;
+use preview;
+use rules::RuleSet;
+use repair;
+use reading;
+use spell;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IssueKind {
+	// The directory cannot be read
+	Walk,
+	// The file cannot be read
+	Parse,
+	// The language cannot be detected
+	Language,
+	// A replacement rule would change the text
+	Text,
+	// Overlap, bad duration, too small gap
+	Timing,
+	// Read too fast
+	Speed,
+	// Not in the dictionary
+	Spelling
+}
+
+impl IssueKind {
+	pub fn name(&self) -> &'static str {
+		match *self {
+			IssueKind::Walk => "walk",
+			IssueKind::Parse => "parse",
+			IssueKind::Language => "language",
+			IssueKind::Text => "text",
+			IssueKind::Timing => "timing",
+			IssueKind::Speed => "speed",
+			IssueKind::Spelling => "spelling"
+		}
+	}
+}
+
+// Something that is wrong in a file. The subtitle number and the line are
+// 0 when unknown.
+#[derive(Debug)]
+pub struct Issue {
+	pub file: String,
+	pub num: u32,
+	pub line_num: u32,
+	pub kind: IssueKind,
+	pub message: String
+}
+
+impl fmt::Display for Issue {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.file)?;
+		if self.line_num > 0 {
+			write!(f, ":{}", self.line_num)?;
+		}
+		write!(f, ": ")?;
+		if self.num > 0 {
+			write!(f, "#{} ", self.num)?;
+		}
+		write!(f, "[{}] {}", self.kind.name(), self.message)
+	}
+}
+
+// Options of the checks
+pub struct CheckOptions<'a> {
+	pub rule_set: &'a RuleSet,
+	pub min_gap: i32,
+	pub min_duration: i32,
+	pub max_cps: f64,
+	pub dictionary: Option<&'a spell::Dictionary>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Lists what is wrong in a file, without changing it
+pub fn check_subtitles(file_path: &str, sub_file: &SubtitleFile, options: &CheckOptions) -> Vec<Issue> {
+	let mut issues: Vec<Issue> = Vec::new();
+
+	for change in preview::text_changes(sub_file, options.rule_set) {
+		issues.push(Issue {
+			file: file_path.to_string(),
+			num: change.num,
+			line_num: change.line_num,
+			kind: IssueKind::Text,
+			message: format!("'{}' -> '{}'", change.before, change.after)
+		});
+	}
+
+	let mut subtitles = sub_file.subtitles.clone();
+	for fix in repair::repair_timing(&mut subtitles, options.min_gap, options.min_duration) {
+		let line_num = sub_file.subtitles.iter()
+			.find(|subtitle| subtitle.num == fix.num)
+			.map_or(0, |subtitle| subtitle.line_num);
+		issues.push(Issue {
+			file: file_path.to_string(),
+			num: fix.num,
+			line_num,
+			kind: IssueKind::Timing,
+			message: fix.describe()
+		});
+	}
+
+	for (subtitle, speed) in sub_file.subtitles.iter().zip(reading::analyse(&sub_file.subtitles)) {
+		if speed.cps > options.max_cps {
+			issues.push(Issue {
+				file: file_path.to_string(),
+				num: subtitle.num,
+				line_num: subtitle.line_num,
+				kind: IssueKind::Speed,
+				message: format!("too fast: {:.1} cps", speed.cps)
+			});
+		}
+	}
+
+	if let Some(dictionary) = options.dictionary {
+		for misspelling in spell::spell_check(sub_file, dictionary, options.rule_set) {
+			issues.push(Issue {
+				file: file_path.to_string(),
+				num: misspelling.num,
+				line_num: misspelling.line_num,
+				kind: IssueKind::Spelling,
+				message: misspelling.describe()
+			});
+		}
+	}
+
+	// In the file order
+	issues.sort_by_key(|issue| issue.line_num);
+	issues
+}
+
+#[test]
+fn test_check_subtitles() {
+	let srt = "1\n00:00:01,000 --> 00:00:03,500\nOk\nCa va\n\n\
+2\n00:00:03,000 --> 00:00:03,500\nThis one is way too fast to be read\n";
+	let sub_file = ::srt::parse_subtitles(srt, ::srt::Format::Srt, None).unwrap();
+	let options = CheckOptions { rule_set: &RuleSet::builtin("fr"), min_gap: 80, min_duration: 1000, max_cps: 17.0, dictionary: None };
+	let issues = check_subtitles("a.srt", &sub_file, &options);
+	assert_eq!(issues.len(), 3);
+	assert_eq!(issues[0].to_string(), "a.srt:3: #1 [timing] overlaps the next one, end 00:00:03,500 -> 00:00:02,920");
+	assert_eq!(issues[1].to_string(), "a.srt:4: #1 [text] 'Ca va' -> 'Ça va'");
+	assert_eq!(issues[2].kind, IssueKind::Speed);
+	assert_eq!(issues[2].line_num, 8);
+}
+
+// Escapes a string for JSON
+fn json_string(text: &str) -> String {
+	let mut ret = String::with_capacity(text.len() + 2);
+	ret.push('"');
+	for c in text.chars() {
+		match c {
+			'"' => ret.push_str("\\\""),
+			'\\' => ret.push_str("\\\\"),
+			'\n' => ret.push_str("\\n"),
+			'\r' => ret.push_str("\\r"),
+			'\t' => ret.push_str("\\t"),
+			c if (c as u32) < 0x20 => ret.push_str(&format!("\\u{:04x}", c as u32)),
+			c => ret.push(c)
+		}
+	}
+	ret.push('"');
+	ret
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Formats issues as a JSON array, one issue per line
+pub fn issues_to_json(issues: &[Issue]) -> String {
+	let mut ret = String::from("[");
+	for (index, issue) in issues.iter().enumerate() {
+		if index > 0 {
+			ret.push(',');
+		}
+		ret.push_str(&format!("\n  {{\"file\": {}, \"subtitle\": {}, \"line\": {}, \"kind\": {}, \"message\": {}}}",
+			json_string(&issue.file), issue.num, issue.line_num,
+			json_string(issue.kind.name()), json_string(&issue.message)));
+	}
+	ret.push_str("\n]");
+	ret
+}
+
+#[test]
+fn test_issues_to_json() {
+	let issues = vec![Issue {
+		file: "dir\\a.srt".to_string(),
+		num: 3,
+		line_num: 12,
+		kind: IssueKind::Text,
+		message: "'\"Ca\"' -> '\"Ça\"'".to_string()
+	}];
+	assert_eq!(issues_to_json(&issues), "[\n  {\"file\": \"dir\\\\a.srt\", \"subtitle\": 3, \"line\": 12, \
+\"kind\": \"text\", \"message\": \"'\\\"Ca\\\"' -> '\\\"Ça\\\"'\"}\n]");
+	assert_eq!(issues_to_json(&[]), "[\n]");
+}
@@ -0,0 +1,274 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::num::ParseIntError;
+use std::str::Utf8Error;
+use std::string::FromUtf16Error;
+
+use regex;
+
+// Error while reading a text file
+#[derive(Debug)]
+pub enum ReadError {
+	Open(io::Error),
+	Read(io::Error),
+	InvalidUtf8(Utf8Error),
+	OddUtf16Length,
+	InvalidUtf16(FromUtf16Error)
+}
+
+impl fmt::Display for ReadError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			ReadError::Open(ref err) => write!(f, "Cannot open file: {}", err),
+			ReadError::Read(ref err) => write!(f, "File read error: {}", err),
+			ReadError::InvalidUtf8(ref err) => write!(f, "Invalid UTF-8: {}", err),
+			ReadError::OddUtf16Length => write!(f, "Invalid UTF-16: odd number of bytes"),
+			ReadError::InvalidUtf16(ref err) => write!(f, "Invalid UTF-16: {}", err)
+		}
+	}
+}
+
+impl error::Error for ReadError {}
+
+// Error in the content of a subtitle file. Line numbers start at 1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+	BadNumber { line_num: usize, text: String, error: ParseIntError },
+	BadDurationSeparator { line_num: usize, text: String },
+	BadTimeFrom { line_num: usize, text: String },
+	BadTimeTo { line_num: usize, text: String },
+	// WebVTT: the first line found, if any
+	MissingVttHeader(Option<String>),
+	MissingCueTiming { line_num: usize },
+	// ASS
+	BadEventFormat { line_num: usize, text: String },
+	MissingEventFields { line_num: usize, text: String },
+	MissingEventFormat,
+	// MicroDVD
+	BadFrames { line_num: usize, text: String },
+	UnknownFrameRate
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			ParseError::BadNumber { line_num, ref text, ref error } =>
+				write!(f, "Bad number at line {}: {}: '{}'", line_num, error, text),
+			ParseError::BadDurationSeparator { line_num, ref text } =>
+				write!(f, "Bad duration separator at line {}: {}", line_num, text),
+			ParseError::BadTimeFrom { line_num, ref text } =>
+				write!(f, "Bad time 'from' at line {}: {}", line_num, text),
+			ParseError::BadTimeTo { line_num, ref text } =>
+				write!(f, "Bad time 'to' at line {}: {}", line_num, text),
+			ParseError::MissingVttHeader(Some(ref text)) => write!(f, "Missing WEBVTT header: {}", text),
+			ParseError::MissingVttHeader(None) => write!(f, "Missing WEBVTT header"),
+			ParseError::MissingCueTiming { line_num } => write!(f, "Missing cue timing at line {}", line_num),
+			ParseError::BadEventFormat { line_num, ref text } =>
+				write!(f, "Bad event format at line {}: {}", line_num, text),
+			ParseError::MissingEventFields { line_num, ref text } =>
+				write!(f, "Missing event fields at line {}: {}", line_num, text),
+			ParseError::MissingEventFormat => write!(f, "Missing [Events] format line"),
+			ParseError::BadFrames { line_num, ref text } => write!(f, "Bad frames at line {}: {}", line_num, text),
+			ParseError::UnknownFrameRate => write!(f, "Unknown frame rate")
+		}
+	}
+}
+
+impl ParseError {
+	// Line of the error, 0 if the error is not on a line
+	pub fn line_num(&self) -> usize {
+		match *self {
+			ParseError::BadNumber { line_num, .. } |
+			ParseError::BadDurationSeparator { line_num, .. } |
+			ParseError::BadTimeFrom { line_num, .. } |
+			ParseError::BadTimeTo { line_num, .. } |
+			ParseError::MissingCueTiming { line_num } |
+			ParseError::BadEventFormat { line_num, .. } |
+			ParseError::MissingEventFields { line_num, .. } |
+			ParseError::BadFrames { line_num, .. } => line_num,
+			ParseError::MissingVttHeader(_) |
+			ParseError::MissingEventFormat |
+			ParseError::UnknownFrameRate => 0
+		}
+	}
+}
+
+impl error::Error for ParseError {}
+
+// An error found while parsing leniently, and skipped
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+	// From 1, in characters
+	pub column: usize,
+	pub error: ParseError
+}
+
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} (column {})", self.error, self.column)
+	}
+}
+
+// Error while loading a subtitle file
+#[derive(Debug)]
+pub enum LoadError {
+	Read(ReadError),
+	Parse(ParseError)
+}
+
+impl fmt::Display for LoadError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			LoadError::Read(ref err) => err.fmt(f),
+			LoadError::Parse(ref err) => err.fmt(f)
+		}
+	}
+}
+
+impl error::Error for LoadError {}
+
+impl From<ReadError> for LoadError {
+	fn from(err: ReadError) -> LoadError {
+		LoadError::Read(err)
+	}
+}
+
+impl From<ParseError> for LoadError {
+	fn from(err: ParseError) -> LoadError {
+		LoadError::Parse(err)
+	}
+}
+
+// Error while saving a subtitle file
+#[derive(Debug)]
+pub enum SaveError {
+	Create(io::Error),
+	Write(io::Error),
+	// The working file could not replace the file
+	Commit(io::Error)
+}
+
+impl fmt::Display for SaveError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			SaveError::Create(ref err) => write!(f, "Cannot create file: {}", err),
+			SaveError::Write(ref err) => write!(f, "Cannot write subtitles: {}", err),
+			SaveError::Commit(ref err) => write!(f, "Cannot replace file: {}", err)
+		}
+	}
+}
+
+impl error::Error for SaveError {}
+
+// Error while listing the files of a directory
+#[derive(Debug)]
+pub struct WalkError {
+	pub path: String,
+	pub error: io::Error
+}
+
+impl fmt::Display for WalkError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}: {}", self.path, self.error)
+	}
+}
+
+impl error::Error for WalkError {}
+
+// Error in replacement rules
+#[derive(Debug)]
+pub enum RuleError {
+	QuoteExpected,
+	BadUnicodeEscape,
+	UnknownEscape,
+	MissingClosingQuote,
+	ParenExpected,
+	UnexpectedText(String),
+	EmptyRule,
+	Regex(regex::Error),
+	// The rule file cannot be read
+	File { path: String, error: io::Error },
+	// Where the error is in a rule file
+	At { path: String, line_num: usize, error: Box<RuleError> }
+}
+
+impl fmt::Display for RuleError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			RuleError::QuoteExpected => write!(f, "\" expected"),
+			RuleError::BadUnicodeEscape => write!(f, "bad \\u{{...}} escape"),
+			RuleError::UnknownEscape => write!(f, "unknown escape"),
+			RuleError::MissingClosingQuote => write!(f, "missing closing \""),
+			RuleError::ParenExpected => write!(f, ") expected"),
+			RuleError::UnexpectedText(ref text) => write!(f, "unexpected text: {}", text),
+			RuleError::EmptyRule => write!(f, "empty rule"),
+			RuleError::Regex(ref err) => err.fmt(f),
+			RuleError::File { ref path, ref error } => write!(f, "{}: {}", path, error),
+			RuleError::At { ref path, line_num, ref error } => write!(f, "{}:{}: {}", path, line_num, error)
+		}
+	}
+}
+
+impl error::Error for RuleError {}
+
+// Error while synchronizing subtitles
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncError {
+	NoSubtitle(u32),
+	SameStart(u32, u32),
+	EmptyReference
+}
+
+impl fmt::Display for SyncError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			SyncError::NoSubtitle(num) => write!(f, "No subtitle #{}", num),
+			SyncError::SameStart(num1, num2) =>
+				write!(f, "Subtitles #{} and #{} start at the same time", num1, num2),
+			SyncError::EmptyReference => write!(f, "No timed subtitle in the reference")
+		}
+	}
+}
+
+impl error::Error for SyncError {}
+
+// Error while loading a Hunspell dictionary
+#[derive(Debug)]
+pub enum SpellError {
+	Read { path: String, error: ReadError },
+	// In the .aff file
+	Affix { line_num: usize, what: &'static str },
+	// In the .dic file
+	BadFlagAlias { line_num: usize }
+}
+
+impl fmt::Display for SpellError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			SpellError::Read { ref path, ref error } => write!(f, "{}: {}", path, error),
+			SpellError::Affix { line_num, what } => write!(f, ".aff:{}: {}", line_num, what),
+			SpellError::BadFlagAlias { line_num } => write!(f, ".dic:{}: bad flag alias", line_num)
+		}
+	}
+}
+
+impl error::Error for SpellError {}
+
+// Error in the hearing-impaired annotation options
+#[derive(Debug)]
+pub enum HiError {
+	OddBrackets,
+	Regex(regex::Error)
+}
+
+impl fmt::Display for HiError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			HiError::OddBrackets => write!(f, "brackets must go by pairs"),
+			HiError::Regex(ref err) => err.fmt(f)
+		}
+	}
+}
+
+impl error::Error for HiError {}
@@ -0,0 +1,169 @@
+use std::fmt;
+
+use srt::{Format, SubtitleFile};
+
+// A frame rate, kept as a fraction so that NTSC rates are exact
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fps {
+	pub num: u32,
+	pub den: u32
+}
+
+impl Fps {
+	// Parses "25", "23.976", "24000/1001" or a preset name: film (24),
+	// ntsc-film (23.976), pal (25), ntsc (29.97).
+	// 23.976, 29.97 and 59.94 are understood as the NTSC rates.
+	pub fn parse(fps_str: &str) -> Option<Fps> {
+		let fps_str = fps_str.trim();
+		let fps = if let Some(slash_index) = fps_str.find('/') {
+			let num = fps_str[..slash_index].trim().parse().ok()?;
+			let den = fps_str[slash_index + 1..].trim().parse().ok()?;
+			Fps { num, den }
+		}
+		else {
+			match fps_str {
+				"film" => Fps { num: 24, den: 1 },
+				"pal" => Fps { num: 25, den: 1 },
+				"23.976" | "23.98" | "ntsc-film" => Fps { num: 24000, den: 1001 },
+				"29.97" | "ntsc" => Fps { num: 30000, den: 1001 },
+				"59.94" => Fps { num: 60000, den: 1001 },
+				_ => {
+					let (int_str, dec_str) = match fps_str.find('.') {
+						Some(dot_index) => (&fps_str[..dot_index], &fps_str[dot_index + 1..]),
+						None => (fps_str, "")
+					};
+					if int_str.is_empty() || dec_str.len() > 6
+					 || !int_str.chars().chain(dec_str.chars()).all(|c| c.is_ascii_digit()) {
+						return None;
+					}
+					let num = format!("{}{}", int_str, dec_str).parse().ok()?;
+					let den = 10u32.pow(dec_str.len() as u32);
+					Fps { num, den }
+				}
+			}
+		};
+		if fps.num == 0 || fps.den == 0 {
+			None
+		}
+		else {
+			Some(fps)
+		}
+	}
+
+	// Time of a frame, rounded to the nearest millisecond. None if it does
+	// not fit in milliseconds.
+	pub fn frame_to_milli(&self, frame: i64) -> Option<i32> {
+		let num = self.num as i128;
+		let milli = (2 * frame as i128 * 1000 * self.den as i128 + num) / (2 * num);
+		if milli >= i32::MIN as i128 && milli <= i32::MAX as i128 {
+			Some(milli as i32)
+		}
+		else {
+			None
+		}
+	}
+
+	// Nearest frame of a time
+	pub fn milli_to_frame(&self, milli: i32) -> i64 {
+		let den = 1000 * self.den as i64;
+		(2 * milli as i64 * self.num as i64 + den) / (2 * den)
+	}
+}
+
+impl fmt::Display for Fps {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.num.is_multiple_of(self.den) {
+			write!(f, "{}", self.num / self.den)
+		}
+		else {
+			let value = format!("{:.3}", self.num as f64 / self.den as f64);
+			write!(f, "{}", value.trim_end_matches('0'))
+		}
+	}
+}
+
+#[test]
+fn test_fps() {
+	assert_eq!(Fps::parse("25"), Some(Fps { num: 25, den: 1 }));
+	assert_eq!(Fps::parse("23.976"), Some(Fps { num: 24000, den: 1001 }));
+	assert_eq!(Fps::parse("24000/1001"), Some(Fps { num: 24000, den: 1001 }));
+	assert_eq!(Fps::parse("12.5"), Some(Fps { num: 125, den: 10 }));
+	assert_eq!(Fps::parse("pal"), Some(Fps { num: 25, den: 1 }));
+	assert_eq!(Fps::parse("ntsc-film"), Some(Fps { num: 24000, den: 1001 }));
+	assert_eq!(Fps::parse("0"), None);
+	assert_eq!(Fps::parse("abc"), None);
+	assert_eq!(Fps::parse(".5"), None);
+	assert_eq!(Fps::parse("-25"), None);
+
+	let pal = Fps::parse("25").unwrap();
+	assert_eq!(pal.frame_to_milli(25), Some(1_000));
+	assert_eq!(pal.frame_to_milli(53_687_091), Some(2_147_483_640));
+	assert_eq!(pal.frame_to_milli(53_687_092), None);
+	assert_eq!(pal.frame_to_milli(i64::MAX), None);
+	assert_eq!(pal.milli_to_frame(1_019), 25);
+	assert_eq!(pal.milli_to_frame(1_020), 26);
+
+	let film = Fps::parse("23.976").unwrap();
+	assert_eq!(film.frame_to_milli(24), Some(1_001));
+	assert_eq!(film.milli_to_frame(1_001), 24);
+	assert_eq!(film.frame_to_milli(1), Some(42));
+
+	assert_eq!(pal.to_string(), "25");
+	assert_eq!(film.to_string(), "23.976");
+	assert_eq!(Fps::parse("29.97").unwrap().to_string(), "29.97");
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Retimes subtitles made for a video at fps_from to the same video played
+// at fps_to. Times are rounded to the nearest millisecond, halves away
+// from zero. Frame based files keep their frame numbers.
+pub fn change_fps(file: &mut SubtitleFile, fps_from: Fps, fps_to: Fps) {
+	let num = fps_from.num as i64 * fps_to.den as i64;
+	let den = fps_from.den as i64 * fps_to.num as i64;
+	let rescale = |milli: i32| -> i32 {
+		let scaled = 2 * milli as i64 * num;
+		let rounded = if scaled < 0 {
+			(scaled - den) / (2 * den)
+		}
+		else {
+			(scaled + den) / (2 * den)
+		};
+		rounded as i32
+	};
+
+	for subtitle in file.subtitles.iter_mut() {
+		subtitle.time_from = rescale(subtitle.time_from);
+		subtitle.time_to = rescale(subtitle.time_to);
+	}
+
+	if file.format == Format::MicroDvd && file.fps.is_some() {
+		file.fps = Some(fps_to);
+		if !file.header.is_empty() {
+			file.header = vec![format!("{{1}}{{1}}{}", fps_to)];
+		}
+	}
+}
+
+#[test]
+fn test_change_fps() {
+	let srt = "1\n00:00:01,000 --> 00:00:02,000\nhi\n\n\
+2\n01:00:00,000 --> -00:00:00,001\nho\n";
+	let mut file = ::srt::parse_subtitles(srt, Format::Srt, None).unwrap();
+	change_fps(&mut file, Fps::parse("pal").unwrap(), Fps::parse("23.976").unwrap());
+	// 1000 * 25 * 1001 / 24000 = 1042.708
+	assert_eq!(file.subtitles[0].time_from, 1_043);
+	assert_eq!(file.subtitles[0].time_to, 2_085);
+	assert_eq!(file.subtitles[1].time_from, 3_753_750);
+	assert_eq!(file.subtitles[1].time_to, -1);
+
+	// Back and forth
+	change_fps(&mut file, Fps::parse("23.976").unwrap(), Fps::parse("pal").unwrap());
+	assert_eq!(file.subtitles[0].time_from, 1_000);
+	assert_eq!(file.subtitles[1].time_from, 3_600_000);
+
+	let mut file = ::srt::parse_subtitles("{1}{1}25\n{25}{50}hi\n", Format::MicroDvd, None).unwrap();
+	change_fps(&mut file, Fps::parse("25").unwrap(), Fps::parse("50").unwrap());
+	assert_eq!(file.subtitles[0].time_from, 500);
+	assert_eq!(file.fps, Fps::parse("50"));
+	assert_eq!(file.header, vec!["{1}{1}50"]);
+}
@@ -0,0 +1,217 @@
+use regex::Regex;
+
+use srt::{Subtitle, SubtitleFile};
+use reading;
+use error::HiError;
+
+// Default annotation brackets, by pairs
+pub const DEFAULT_BRACKETS: &str = "[]()♪♪♫♫";
+
+// Default speaker label: JOHN:, MAN 2:, DR. WHO:
+pub const DEFAULT_LABEL: &str = r"^\p{Lu}[\p{Lu}\d .'-]*[\p{Lu}\d]:\s*";
+
+// What hearing-impaired annotations look like
+pub struct HiOptions {
+	// Opening and closing characters
+	pub brackets: Vec<(char, char)>,
+	// Matches a speaker label at the start of a line
+	pub label: Option<Regex>
+}
+
+impl HiOptions {
+	// brackets are given by pairs of characters, an empty label disables
+	// the label removal
+	pub fn new(brackets: &str, label: &str) -> Result<HiOptions, HiError> {
+		let chars: Vec<char> = brackets.chars().collect();
+		if !chars.len().is_multiple_of(2) {
+			return Err(HiError::OddBrackets);
+		}
+		let label = if label.is_empty() {
+			None
+		}
+		else {
+			Some(Regex::new(label).map_err(HiError::Regex)?)
+		};
+		Ok(HiOptions {
+			brackets: chars.chunks(2).map(|pair| (pair[0], pair[1])).collect(),
+			label
+		})
+	}
+}
+
+// Removes the bracketed annotations of a text, which can span several
+// lines. A bracket that is not closed goes to the end of the line. Returns
+// the number of annotations removed.
+fn remove_brackets(text: &str, brackets: &[(char, char)]) -> (String, usize) {
+	let mut ret = String::with_capacity(text.len());
+	let mut count = 0;
+	let mut closing: Option<char> = None;
+	let mut just_removed = false;
+	for (index, c) in text.char_indices() {
+		match closing {
+			Some(close) => {
+				if c == close {
+					closing = None;
+					just_removed = true;
+				}
+				else if c == '\n' && !text[index..].contains(close) {
+					closing = None;
+					ret.push(c);
+				}
+			},
+			None => match brackets.iter().find(|&&(open, _)| open == c) {
+				Some(&(_, close)) => {
+					closing = Some(close);
+					count += 1;
+				},
+				None => {
+					// "Hey (coughs)." gives "Hey."
+					if just_removed && ".,!?;:…".contains(c) && ret.ends_with(' ') {
+						ret.pop();
+					}
+					if c != ' ' {
+						just_removed = false;
+					}
+					ret.push(c);
+				}
+			}
+		}
+	}
+	(ret, count)
+}
+
+// Splits the formatting tags at the start of a line: "<i>- Hi" gives
+// ("<i>", "- Hi")
+fn split_leading_tags(line: &str) -> (&str, &str) {
+	let mut end = 0;
+	loop {
+		let rest = &line[end..];
+		let close = match rest.chars().next() {
+			Some('<') => '>',
+			Some('{') => '}',
+			_ => break
+		};
+		match rest.find(close) {
+			Some(close_index) => end += close_index + 1,
+			None => break
+		}
+	}
+	(&line[..end], &line[end..])
+}
+
+fn is_dash(c: char) -> bool {
+	c == '-' || c == '–' || c == '—'
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Removes the annotations of a subtitle. Returns the number of annotations
+// removed.
+pub fn strip_subtitle(subtitle: &mut Subtitle, options: &HiOptions) -> usize {
+	let text = subtitle.texts.join("\n");
+	let (text, mut count) = remove_brackets(&text, &options.brackets);
+
+	// Lines left, and if they start a speech
+	let mut lines: Vec<(String, bool)> = Vec::new();
+	for line in text.split('\n') {
+		let (tags, rest) = split_leading_tags(line);
+		let mut rest = rest.trim_start();
+		let mut speaker = false;
+		if rest.starts_with(is_dash) {
+			rest = rest.trim_start_matches(is_dash).trim_start();
+			speaker = true;
+		}
+		if let Some(ref label) = options.label {
+			if let Some(found) = label.find(rest) {
+				rest = &rest[found.end()..];
+				speaker = true;
+				count += 1;
+			}
+		}
+		let words: Vec<&str> = rest.split(' ').filter(|word| !word.is_empty()).collect();
+		let line = format!("{}{}", tags, words.join(" "));
+		if !reading::strip_tags(&line).trim().is_empty() {
+			lines.push((line, speaker));
+		}
+	}
+	if count == 0 {
+		return 0;
+	}
+
+	// Dashes only when several people speak
+	let speakers = lines.iter().filter(|&&(_, speaker)| speaker).count();
+	let use_dashes = speakers >= 2;
+	subtitle.texts.clear();
+	for (line, speaker) in lines {
+		let line = if use_dashes && speaker {
+			let (tags, rest) = split_leading_tags(&line);
+			format!("{}- {}", tags, rest)
+		}
+		else {
+			line
+		};
+		subtitle.push_text(&line);
+	}
+	count
+}
+
+#[test]
+fn test_strip_subtitle() {
+	let options = HiOptions::new(DEFAULT_BRACKETS, DEFAULT_LABEL).unwrap();
+	let strip = |texts: &[&str]| -> Vec<String> {
+		let mut subtitle = Subtitle::default();
+		for text in texts.iter() {
+			subtitle.push_text(text);
+		}
+		strip_subtitle(&mut subtitle, &options);
+		subtitle.texts.clone()
+	};
+	assert_eq!(strip(&["[DOOR SLAMS]", "Who's there?"]), vec!["Who's there?"]);
+	assert_eq!(strip(&["(laughs) That's funny."]), vec!["That's funny."]);
+	assert_eq!(strip(&["♪ Happy birthday ♪"]), Vec::<String>::new());
+	assert_eq!(strip(&["♪ Happy birthday", "to you ♪"]), Vec::<String>::new());
+	assert_eq!(strip(&["JOHN: Hi.", "MARY: Hello."]), vec!["- Hi.", "- Hello."]);
+	assert_eq!(strip(&["- Hi.", "- [SIGHS]"]), vec!["Hi."]);
+	assert_eq!(strip(&["<i>- Hi.</i>", "<i>- MAN 2: Hey (coughs).</i>"]), vec!["<i>- Hi.</i>", "<i>- Hey.</i>"]);
+	assert_eq!(strip(&["Hi, John: come in."]), vec!["Hi, John: come in."]);
+	assert_eq!(strip(&["<i>[music]</i>"]), Vec::<String>::new());
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Removes the annotations of all the subtitles, and the subtitles left
+// without text. The other subtitles keep their number. Returns the number of
+// annotations removed and the numbers of the subtitles removed.
+pub fn strip_hi(file: &mut SubtitleFile, options: &HiOptions) -> (usize, Vec<u32>) {
+	let mut count = 0;
+	let mut removed: Vec<u32> = Vec::new();
+	let mut kept: Vec<Subtitle> = Vec::with_capacity(file.subtitles.len());
+	// Notes of the removed subtitles go to the next one
+	let mut notes: Vec<String> = Vec::new();
+	for mut subtitle in file.subtitles.drain(..) {
+		let had_text = !subtitle.texts.is_empty();
+		count += strip_subtitle(&mut subtitle, options);
+		notes.append(&mut subtitle.notes);
+		if had_text && subtitle.texts.is_empty() {
+			removed.push(subtitle.num);
+			continue;
+		}
+		subtitle.notes = ::std::mem::take(&mut notes);
+		kept.push(subtitle);
+	}
+	notes.append(&mut file.notes);
+	file.notes = notes;
+	file.subtitles = kept;
+	(count, removed)
+}
+
+#[test]
+fn test_strip_hi() {
+	let srt = "1\n00:00:01,000 --> 00:00:02,000\n[THUNDER]\n\n\
+2\n00:00:03,000 --> 00:00:04,000\nJOHN: Run!\n";
+	let mut file = ::srt::parse_subtitles(srt, ::srt::Format::Srt, None).unwrap();
+	let options = HiOptions::new(DEFAULT_BRACKETS, DEFAULT_LABEL).unwrap();
+	assert_eq!(strip_hi(&mut file, &options), (2, vec![1]));
+	assert_eq!(file.subtitles.len(), 1);
+	assert_eq!(file.subtitles[0].num, 2);
+	assert_eq!(file.subtitles[0].texts[0], "Run!");
+	assert!(HiOptions::new("[", "").is_err());
+}
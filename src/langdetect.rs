@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use srt::Subtitle;
+use reading;
+
+// Dialogue like texts the language profiles are built from
+const SAMPLES: &[(&str, &str)] = &[
+	("fr", "Je ne sais pas ce que tu veux dire. Il faut que tu partes maintenant, \
+avant qu'ils arrivent. Tu crois vraiment que c'est une bonne idée ? On n'a pas \
+le choix, je te le dis. Elle est partie hier soir avec son frère et ils ne sont \
+jamais revenus. Qu'est-ce qui s'est passé ? Personne ne le sait. Écoute-moi bien, \
+nous allons trouver une solution ensemble. Je suis désolé, je ne voulais pas te \
+faire de mal. Où est-ce que vous étiez pendant tout ce temps ? Nous avons cherché \
+partout dans la ville. C'est pour ça que je suis venu te voir. Laisse-moi \
+tranquille, s'il te plaît. Tu es sûr de vouloir faire ça ? Oui, bien sûr, \
+pourquoi pas. Il y a quelque chose qui ne va pas chez lui. Merci beaucoup, \
+c'est très gentil de votre part. On se voit demain matin au bureau."),
+	("en", "I don't know what you mean. You have to leave now, before they get \
+here. Do you really think that's a good idea? We don't have a choice, I'm telling \
+you. She left last night with her brother and they never came back. What \
+happened? Nobody knows. Listen to me, we are going to find a way out of this \
+together. I'm sorry, I didn't want to hurt you. Where have you been all this \
+time? We looked everywhere in the city. That's why I came to see you. Leave me \
+alone, please. Are you sure you want to do this? Yes, of course, why not. There \
+is something wrong with him. Thank you very much, that's very kind of you. I'll \
+see you tomorrow morning at the office. What are we going to do now?"),
+	("es", "No sé lo que quieres decir. Tienes que irte ahora, antes de que \
+lleguen. ¿De verdad crees que es una buena idea? No tenemos elección, te lo digo. \
+Ella se fue anoche con su hermano y nunca volvieron. ¿Qué pasó? Nadie lo sabe. \
+Escúchame bien, vamos a encontrar una solución juntos. Lo siento, no quería \
+hacerte daño. ¿Dónde estabais durante todo este tiempo? Buscamos por toda la \
+ciudad. Por eso vine a verte. Déjame en paz, por favor. ¿Estás seguro de que \
+quieres hacer esto? Sí, claro, por qué no. Hay algo que no va bien con él. \
+Muchas gracias, es muy amable de su parte. Nos vemos mañana por la mañana en la \
+oficina. ¿Qué vamos a hacer ahora?"),
+	("de", "Ich weiß nicht, was du meinst. Du musst jetzt gehen, bevor sie \
+kommen. Glaubst du wirklich, dass das eine gute Idee ist? Wir haben keine Wahl, \
+das sage ich dir. Sie ist gestern Abend mit ihrem Bruder weggegangen und sie \
+sind nie zurückgekommen. Was ist passiert? Niemand weiß es. Hör mir gut zu, wir \
+werden zusammen eine Lösung finden. Es tut mir leid, ich wollte dir nicht wehtun. \
+Wo wart ihr die ganze Zeit? Wir haben überall in der Stadt gesucht. Deshalb bin \
+ich zu dir gekommen. Lass mich bitte in Ruhe. Bist du sicher, dass du das tun \
+willst? Ja, natürlich, warum nicht. Mit ihm stimmt etwas nicht. Vielen Dank, das \
+ist sehr nett von Ihnen. Wir sehen uns morgen früh im Büro."),
+	("it", "Non so cosa vuoi dire. Devi andartene adesso, prima che arrivino. \
+Pensi davvero che sia una buona idea? Non abbiamo scelta, te lo dico io. Lei è \
+partita ieri sera con suo fratello e non sono mai tornati. Che cosa è successo? \
+Nessuno lo sa. Ascoltami bene, troveremo una soluzione insieme. Mi dispiace, non \
+volevo farti del male. Dove siete stati per tutto questo tempo? Abbiamo cercato \
+dappertutto in città. È per questo che sono venuto a trovarti. Lasciami in pace, \
+per favore. Sei sicuro di volerlo fare? Sì, certo, perché no. C'è qualcosa che \
+non va in lui. Grazie mille, è molto gentile da parte sua. Ci vediamo domani \
+mattina in ufficio. Che cosa facciamo adesso?"),
+	("pt", "Eu não sei o que você quer dizer. Você tem que ir embora agora, antes \
+que eles cheguem. Você acha mesmo que é uma boa ideia? Não temos escolha, estou \
+te dizendo. Ela saiu ontem à noite com o irmão e eles nunca voltaram. O que \
+aconteceu? Ninguém sabe. Escute bem, nós vamos encontrar uma solução juntos. Me \
+desculpe, eu não queria te machucar. Onde vocês estavam durante todo esse tempo? \
+Procuramos por toda a cidade. Foi por isso que eu vim te ver. Me deixe em paz, \
+por favor. Tem certeza de que quer fazer isso? Sim, claro, por que não. Tem \
+alguma coisa errada com ele. Muito obrigado, é muito gentil da sua parte. A \
+gente se vê amanhã de manhã no escritório. O que vamos fazer agora?")
+];
+
+// Below, the detected language is not trusted
+pub const MIN_CONFIDENCE: f64 = 0.15;
+
+// Result of the detection
+#[derive(Debug, Clone, Copy)]
+pub struct Detection {
+	pub code: &'static str,
+	// From 0 (no idea) to 1 (sure)
+	pub confidence: f64
+}
+
+// Counts the character trigrams of a text, words are padded with spaces
+fn trigrams(text: &str) -> HashMap<[char; 3], f64> {
+	let mut counts: HashMap<[char; 3], f64> = HashMap::new();
+	for word in text.split(|c: char| !c.is_alphabetic() && c != '\'') {
+		if word.is_empty() {
+			continue;
+		}
+		let mut chars: Vec<char> = vec![' '];
+		chars.extend(word.chars().flat_map(|c| c.to_lowercase()));
+		chars.push(' ');
+		for window in chars.windows(3) {
+			*counts.entry([window[0], window[1], window[2]]).or_insert(0.0) += 1.0;
+		}
+	}
+	counts
+}
+
+fn cosine(a: &HashMap<[char; 3], f64>, b: &HashMap<[char; 3], f64>) -> f64 {
+	let dot: f64 = a.iter().filter_map(|(key, count)| b.get(key).map(|other| count * other)).sum();
+	let norm_a: f64 = a.values().map(|count| count * count).sum::<f64>().sqrt();
+	let norm_b: f64 = b.values().map(|count| count * count).sum::<f64>().sqrt();
+	if norm_a == 0.0 || norm_b == 0.0 {
+		0.0
+	}
+	else {
+		dot / (norm_a * norm_b)
+	}
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Guesses the language of a text. The confidence is the relative distance
+// between the best and the second best similarity, and it is lower for short
+// texts.
+pub fn detect_text(text: &str) -> Detection {
+	let counts = trigrams(text);
+	let mut scores: Vec<(&'static str, f64)> = SAMPLES.iter()
+		.map(|&(code, sample)| (code, cosine(&counts, &trigrams(sample))))
+		.collect();
+	scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+	let (code, best) = scores[0];
+	let second = scores[1].1;
+	if best <= 0.0 {
+		return Detection { code, confidence: 0.0 };
+	}
+	// Under 50 trigrams, about ten words, the text is too short to be sure
+	let total: f64 = counts.values().sum();
+	let length_factor = (total / 50.0).min(1.0);
+	Detection {
+		code,
+		confidence: (best - second) / best * length_factor
+	}
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Guesses the language of subtitles
+pub fn detect_language(subtitles: &[Subtitle]) -> Detection {
+	let mut text = String::new();
+	for subtitle in subtitles.iter() {
+		for line in subtitle.texts.iter() {
+			text.push_str(&reading::strip_tags(line));
+			text.push('\n');
+		}
+	}
+	detect_text(&text)
+}
+
+#[test]
+fn test_detect_text() {
+	let check = |text: &str, code: &str| {
+		let detection = detect_text(text);
+		assert_eq!(detection.code, code);
+		assert!(detection.confidence >= MIN_CONFIDENCE, "{} {}", text, detection.confidence);
+	};
+	check("Bonjour, comment allez-vous ? Je voudrais savoir pourquoi vous êtes \
+venus ici ce soir, il est déjà très tard.", "fr");
+	check("Hello, how are you? I would like to know why you came here tonight, \
+it is already very late.", "en");
+	check("Hola, ¿cómo estáis? Me gustaría saber por qué habéis venido aquí esta \
+noche, ya es muy tarde.", "es");
+	check("Hallo, wie geht es Ihnen? Ich möchte wissen, warum Sie heute Abend \
+hierher gekommen sind, es ist schon sehr spät.", "de");
+	check("Ciao, come state? Vorrei sapere perché siete venuti qui stasera, è \
+già molto tardi.", "it");
+	check("Olá, como vocês estão? Eu gostaria de saber por que vocês vieram \
+aqui esta noite, já é muito tarde.", "pt");
+
+	assert!(detect_text("Ok").confidence < MIN_CONFIDENCE);
+	assert_eq!(detect_text("123 ...").confidence, 0.0);
+}
@@ -0,0 +1,33 @@
+// fixsrt library: subtitle file model, parsers and writers, time operations
+// and replacement rules. The fixsrt command line tool is built on it.
+
+extern crate regex;
+
+mod workfile;
+pub mod error;
+pub mod charset;
+pub mod srt;
+pub mod vtt;
+pub mod ass;
+pub mod microdvd;
+pub mod fps;
+pub mod sync;
+pub mod repair;
+pub mod normalize;
+pub mod reading;
+pub mod check;
+pub mod preview;
+pub mod langdetect;
+pub mod spell;
+pub mod hi;
+pub mod wrap;
+pub mod txt_rep;
+pub mod walk;
+pub mod rules;
+
+pub use srt::{Format, ParseMode, Subtitle, SubtitleFile, parse_srt, parse_srt_lenient, parse_srt_time,
+	parse_subtitles, format_subtitles, load_subtitles, save_subtitles, shift_and_stretch};
+pub use rules::RuleSet;
+pub use txt_rep::{replace_one, replace_subtitles};
+pub use error::{Diagnostic, HiError, LoadError, ParseError, ReadError, RuleError, SaveError, SpellError, SyncError,
+	WalkError};
@@ -1,78 +1,98 @@
 extern crate clap;
+extern crate fixsrt;
+
+use std::collections::BTreeMap;
+use std::path::Path;
 
 use clap::{Arg, App, Error};
 
-mod workfile;
-mod srt;
-mod txt_rep;
-mod rules;
+use fixsrt::{charset, check, error, fps, hi, langdetect, normalize, preview, reading, repair, rules, spell, srt, sync, txt_rep,
+	walk, wrap};
 
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
-///////////////////////////////////////////////////////////////////////////////
-fn do_replacements(subtitles: &mut Vec<srt::Subtitle>, language: &str) {
-
-	for subtitle in subtitles.iter_mut() {
-		for text_index in 0..subtitle.text_count as usize {
-			subtitle.texts[text_index] = txt_rep::replace_one(&subtitle.texts[text_index], &language);
-		}
-		//print!("{}", subtitle.to_string());
+// The error message, with the option that helps if any
+fn load_error_message(err: &error::LoadError) -> String {
+	match *err {
+		error::LoadError::Parse(error::ParseError::UnknownFrameRate) => format!("{}, use --fps", err),
+		_ => err.to_string()
 	}
 }
 
-fn do_time_changes(subtitles: &mut Vec<srt::Subtitle>,
-                   time_shift_ms: i32, time_stretch_ms: i32) {
-
-	if time_shift_ms == 0 && time_stretch_ms == 0 {
-		return;
+// Language of a file: the --lang one, or the detected one with a message.
+// Fails when the detection is not trusted, and the file is skipped.
+fn file_language(fixed_language: Option<&'static rules::Language>, subtitles: &[srt::Subtitle])
+                 -> Result<(&'static str, Option<String>), String> {
+	if let Some(language) = fixed_language {
+		return Ok((language.code, None));
 	}
-	let sub_count = subtitles.len();
-	if sub_count == 0 {
-		return;
-	}
-
-	if sub_count > std::i32::MAX as usize {
-		eprintln!("Too many subtitles");
-		return;
-	};
-
-	// Compute the limit for stretching
-	let sub_count_i32 = if srt::should_keep_last_sub(subtitles) {
-		sub_count as i32
+	let detection = langdetect::detect_language(subtitles);
+	let name = rules::find_language(detection.code).map_or(detection.code, |language| language.name);
+	if detection.confidence < langdetect::MIN_CONFIDENCE {
+		Err(format!("language not detected ({}?, confidence {:.2}), use --lang",
+			name, detection.confidence))
 	}
 	else {
-		(sub_count - 1) as i32
-	};
-
-	let mut sub_index = 0;
-	for subtitle in subtitles.iter_mut() {
+		Ok((detection.code, Some(format!("language: {}, confidence {:.2}", name, detection.confidence))))
+	}
+}
 
-		subtitle.time_from += time_shift_ms;
-		subtitle.time_to += time_shift_ms;
+#[test]
+fn test_file_language() {
+	let subtitles = |text: &str| vec![srt::Subtitle { num: 1, texts: vec![text.to_string()], ..Default::default() }];
+	let french = subtitles("Je ne sais pas où il est allé, mais il reviendra demain avec les enfants.");
+	assert_eq!(file_language(None, &french).unwrap().0, "fr");
+	let unknown = subtitles("OK 123");
+	assert!(file_language(None, &unknown).unwrap_err().starts_with("language not detected"));
+	assert_eq!(file_language(rules::find_language("en"), &unknown), Ok(("en", None)));
+}
 
-		if time_stretch_ms != 0 && sub_count_i32 > 1 && sub_index < sub_count_i32 {
-			let stretching = (sub_index * time_stretch_ms) / (sub_count_i32 - 1);
-			subtitle.time_from += stretching;
-			subtitle.time_to += stretching;
-		}
-		sub_index += 1;
-	}
+// What happened to a file
+#[derive(Clone, Copy, PartialEq)]
+enum Outcome {
+	Unchanged,
+	Changed,
+	// Left as is, its language being unknown
+	Skipped,
+	Failed
 }
 
+// Files of a directory, printed once all the files are done
+#[derive(Default)]
+struct DirSummary {
+	processed: u32,
+	changed: u32,
+	skipped: u32,
+	failed: u32
+}
 
 ///////////////////////////////////////////////////////////////////////////////
 fn main() {
 	let matches = App::new("fixsrt")
 		.version(VERSION)
 		.author("Hadrien Nilsson")
-		.about("Fix spelling and encoding mistakes in SRT subtitle files")
+		.about("Fix spelling and encoding mistakes in SRT, WebVTT, ASS and MicroDVD subtitle files")
 		.arg(Arg::with_name("nobak")
 			.long("nobak")
 			.help("Avoids creating a backup file"))
 		.arg(Arg::with_name("SRTFILE")
 			.required(true)
 			.multiple(true)
-			.help("SRT file to update"))
+			.help("SRT file to update, or directory to update the files of"))
+		.arg(Arg::with_name("include")
+			.long("include")
+			.takes_value(true)
+			.multiple(true)
+			.number_of_values(1)
+			.value_name("PATTERN")
+			.help("Takes only the files of the directories whose name matches a pattern like '*.fr.srt'"))
+		.arg(Arg::with_name("exclude")
+			.long("exclude")
+			.takes_value(true)
+			.multiple(true)
+			.number_of_values(1)
+			.value_name("PATTERN")
+			.help("Leaves out the files of the directories whose name matches a pattern"))
 		.arg(Arg::with_name("out")
 			.short("o")
 			.long("out")
@@ -88,14 +108,155 @@ fn main() {
 			.takes_value(true)
 			.allow_hyphen_values(true)
 			.help("Stretches all subtitles after adding several seconds to the last one"))
+		.arg(Arg::with_name("sync")
+			.long("sync")
+			.takes_value(true)
+			.number_of_values(2)
+			.value_names(&["NUM=TIME", "NUM=TIME"])
+			.conflicts_with("stretch")
+			.help("Moves and stretches all subtitles so that two subtitles start at the given times"))
+		.arg(Arg::with_name("sync-to")
+			.long("sync-to")
+			.takes_value(true)
+			.value_name("REFFILE")
+			.conflicts_with_all(&["sync", "stretch"])
+			.help("Retimes the subtitles after a correctly timed subtitle file of the same video"))
+		.arg(Arg::with_name("normalize")
+			.long("normalize")
+			.help("Sorts the subtitles by start time, removes the exact duplicates and renumbers them from 1"))
+		.arg(Arg::with_name("fix-timing")
+			.long("fix-timing")
+			.help("Fixes overlapping subtitles, bad durations and too small gaps"))
+		.arg(Arg::with_name("min-gap")
+			.long("min-gap")
+			.takes_value(true)
+			.help("Minimum gap between two subtitles, in seconds or frames (2f), 0.08 by default"))
+		.arg(Arg::with_name("min-duration")
+			.long("min-duration")
+			.takes_value(true)
+			.requires("fix-timing")
+			.help("Minimum duration of a subtitle, in seconds or frames, 1 by default"))
+		.arg(Arg::with_name("cps-report")
+			.long("cps-report")
+			.help("Reports the reading speed of each subtitle"))
+		.arg(Arg::with_name("max-cps")
+			.long("max-cps")
+			.takes_value(true)
+			.help("Maximum reading speed in characters per second, 17 by default"))
+		.arg(Arg::with_name("fix-cps")
+			.long("fix-cps")
+			.help("Extends the subtitles read too fast into the free time after them"))
+		.arg(Arg::with_name("check")
+			.long("check")
+			.help("Lists the problems of the file(s) without changing them, fails if there are some"))
+		.arg(Arg::with_name("json")
+			.long("json")
+			.requires("check")
+			.help("Lists the problems as JSON"))
+		.arg(Arg::with_name("dry-run")
+			.long("dry-run")
+			.help("Shows the text changes and the rules that make them, without changing the file(s)"))
+		.arg(Arg::with_name("diff")
+			.long("diff")
+			.help("Like --dry-run, as a unified diff"))
+		.arg(Arg::with_name("strip-hi")
+			.long("strip-hi")
+			.help("Removes hearing-impaired annotations: [DOOR SLAMS], (laughs), ♪ lyrics ♪, JOHN:"))
+		.arg(Arg::with_name("hi-brackets")
+			.long("hi-brackets")
+			.takes_value(true)
+			.requires("strip-hi")
+			.help("Brackets of the annotations, by pairs, \"[]()♪♪♫♫\" by default"))
+		.arg(Arg::with_name("hi-label")
+			.long("hi-label")
+			.takes_value(true)
+			.requires("strip-hi")
+			.help("Regular expression of the speaker labels at the start of a line, \"\" for none"))
+		.arg(Arg::with_name("wrap")
+			.long("wrap")
+			.help("Breaks the lines again, at the best places, in as few lines as possible"))
+		.arg(Arg::with_name("max-chars")
+			.long("max-chars")
+			.takes_value(true)
+			.requires("wrap")
+			.help("Maximum number of characters of a line, 42 by default"))
+		.arg(Arg::with_name("max-lines")
+			.long("max-lines")
+			.takes_value(true)
+			.requires("wrap")
+			.help("Maximum number of lines of a subtitle, 2 by default"))
+		.arg(Arg::with_name("dict")
+			.long("dict")
+			.takes_value(true)
+			.value_name("DICFILE")
+			.help("Checks the spelling with a Hunspell dictionary (.dic file, the .aff file is next to it)"))
+		.arg(Arg::with_name("words")
+			.long("words")
+			.takes_value(true)
+			.value_name("FILE")
+			.requires("dict")
+			.help("Words to accept, like names, one per line"))
+		.arg(Arg::with_name("spell-fix")
+			.long("spell-fix")
+			.requires("dict")
+			.help("Restores the accents of unknown words when the dictionary knows only one way"))
 		.arg(Arg::with_name("lang")
 			.long("lang")
 			.takes_value(true)
-			.help("Selects the language of the SRT file(s) (fr/en)"))
+			.help("Selects the language of the SRT file(s) (fr/en/es/de/it/pt), detected by default"))
+		.arg(Arg::with_name("rules")
+			.long("rules")
+			.takes_value(true)
+			.multiple(true)
+			.number_of_values(1)
+			.value_name("[LANG:]FILE")
+			.help("Adds or overrides replacement rules with the rules of a file, for one language"))
+		.arg(Arg::with_name("format")
+			.long("format")
+			.takes_value(true)
+			.help("Selects the format of the input file(s) (srt/vtt/ass/sub), guessed from the extension by default"))
+		.arg(Arg::with_name("strict")
+			.long("strict")
+			.help("Fails on the first error of a SRT file instead of skipping the broken subtitles"))
+		.arg(Arg::with_name("input-encoding")
+			.long("input-encoding")
+			.takes_value(true)
+			.help("Character encoding of the input file(s), detected by default"))
+		.arg(Arg::with_name("encoding")
+			.long("encoding")
+			.takes_value(true)
+			.help("Character encoding of the output file(s) (utf-8/utf-16le/utf-16be/windows-1252/iso-8859-15/windows-1250/windows-1251), same as the input by default"))
+		.arg(Arg::with_name("bom")
+			.long("bom")
+			.conflicts_with("no-bom")
+			.help("Writes a byte order mark at the start of UTF-8 output files"))
+		.arg(Arg::with_name("no-bom")
+			.long("no-bom")
+			.help("Writes UTF-8 output files without a byte order mark"))
+		.arg(Arg::with_name("eol")
+			.long("eol")
+			.takes_value(true)
+			.help("Line endings of the output file(s) (lf/crlf), same as the input by default"))
+		.arg(Arg::with_name("fps")
+			.long("fps")
+			.takes_value(true)
+			.help("Frame rate of MicroDVD files, when not given by the file itself"))
+		.arg(Arg::with_name("fps-from")
+			.long("fps-from")
+			.takes_value(true)
+			.requires("fps-to")
+			.help("Frame rate the subtitles were made for (number, film, ntsc-film, pal or ntsc)"))
+		.arg(Arg::with_name("fps-to")
+			.long("fps-to")
+			.takes_value(true)
+			.requires("fps-from")
+			.help("Frame rate of the video to retime the subtitles for"))
 		.get_matches();
 
 	let no_backup = matches.is_present("nobak");
-	let in_file_paths: Vec<_> = matches.values_of("SRTFILE").unwrap().collect();
+	let in_paths: Vec<_> = matches.values_of("SRTFILE").unwrap().collect();
+	let include: Vec<&str> = matches.values_of("include").map(|values| values.collect()).unwrap_or_default();
+	let exclude: Vec<&str> = matches.values_of("exclude").map(|values| values.collect()).unwrap_or_default();
 	let out_file_path = matches.value_of("out");
 	let time_shift_ms = match matches.value_of("shift") {
 		Some(tos) => match srt::parse_srt_time(tos) {
@@ -121,37 +282,395 @@ fn main() {
 		},
 		None => 0
 	};
-	let language = matches.value_of("lang").unwrap_or("fr");
+	let sync_anchors: Option<Vec<sync::Anchor>> = matches.values_of("sync").map(|values| {
+		values.map(|value| match sync::Anchor::parse(value) {
+			Some(anchor) => anchor,
+			None => {
+				let err = Error { message: "--sync invalid argument".into(),
+					kind: clap::ErrorKind::InvalidValue,
+					info: None };
+				err.exit();
+			}
+		}).collect()
+	});
+	let sync_ref_path = matches.value_of("sync-to");
+	let normalize = matches.is_present("normalize");
+	let fix_timing = matches.is_present("fix-timing");
+	let parse_length_arg = |name: &str, default: repair::Length| match matches.value_of(name) {
+		Some(length_str) => match repair::Length::parse(length_str) {
+			Some(length) => length,
+			None => {
+				let err = Error { message: format!("--{} invalid argument", name),
+					kind: clap::ErrorKind::InvalidValue,
+					info: None };
+				err.exit();
+			}
+		},
+		None => default
+	};
+	let min_gap = parse_length_arg("min-gap", repair::Length::Milli(80));
+	let min_duration = parse_length_arg("min-duration", repair::Length::Milli(1000));
+	let check = matches.is_present("check");
+	let json = matches.is_present("json");
+	let dry_run = matches.is_present("dry-run");
+	let diff = matches.is_present("diff");
+	let cps_report = matches.is_present("cps-report");
+	let fix_cps = matches.is_present("fix-cps");
+	let max_cps: f64 = match matches.value_of("max-cps") {
+		Some(cps_str) => match cps_str.parse() {
+			Ok(cps) if cps > 0.0 => cps,
+			_ => {
+				let err = Error { message: "--max-cps invalid argument".into(),
+					kind: clap::ErrorKind::InvalidValue,
+					info: None };
+				err.exit();
+			}
+		},
+		None => 17.0
+	};
+	let wrap = matches.is_present("wrap");
+	let parse_count_arg = |name: &str, default: usize| match matches.value_of(name) {
+		Some(count_str) => match count_str.parse() {
+			Ok(count) if count > 0 => count,
+			_ => {
+				let err = Error { message: format!("--{} invalid argument", name),
+					kind: clap::ErrorKind::InvalidValue,
+					info: None };
+				err.exit();
+			}
+		},
+		None => default
+	};
+	let max_chars = parse_count_arg("max-chars", 42);
+	let max_lines = parse_count_arg("max-lines", 2);
+	let language = matches.value_of("lang");
+	let parse_encoding_arg = |name: &str| match matches.value_of(name) {
+		Some(encoding_name) => match charset::Encoding::from_name(encoding_name) {
+			Some(encoding) => Some(encoding),
+			None => {
+				let err = Error { message: format!("--{} invalid argument", name),
+					kind: clap::ErrorKind::InvalidValue,
+					info: None };
+				err.exit();
+			}
+		},
+		None => None
+	};
+	let input_encoding = parse_encoding_arg("input-encoding");
+	let parse_mode = if matches.is_present("strict") { srt::ParseMode::Strict } else { srt::ParseMode::Lenient };
+	let encoding = parse_encoding_arg("encoding");
+	let bom = if matches.is_present("bom") {
+		Some(true)
+	}
+	else if matches.is_present("no-bom") {
+		Some(false)
+	}
+	else {
+		None
+	};
+	let eol = match matches.value_of("eol") {
+		Some(name) => match charset::Eol::from_name(name) {
+			Some(eol) => Some(eol),
+			None => {
+				let err = Error { message: "--eol invalid argument".into(),
+					kind: clap::ErrorKind::InvalidValue,
+					info: None };
+				err.exit();
+			}
+		},
+		None => None
+	};
+	let format = match matches.value_of("format") {
+		Some(name) => match srt::Format::from_name(name) {
+			Some(format) => Some(format),
+			None => {
+				let err = Error { message: "--format invalid argument".into(),
+					kind: clap::ErrorKind::InvalidValue,
+					info: None };
+				err.exit();
+			}
+		},
+		None => None
+	};
+	let parse_fps_arg = |name: &str| match matches.value_of(name) {
+		Some(fps_str) => match fps::Fps::parse(fps_str) {
+			Some(fps) => Some(fps),
+			None => {
+				let err = Error { message: format!("--{} invalid argument", name),
+					kind: clap::ErrorKind::InvalidValue,
+					info: None };
+				err.exit();
+			}
+		},
+		None => None
+	};
+	let fps = parse_fps_arg("fps");
+	let fps_from = parse_fps_arg("fps-from");
+	let fps_to = parse_fps_arg("fps-to");
 
-	if language != "en" && language != "fr" {
-		println!("Bad language");
-		std::process::exit(1);
+	let fixed_language = language.map(|code| match rules::find_language(code) {
+		Some(language) => language,
+		None => {
+			let codes: Vec<String> = rules::LANGUAGES.iter()
+				.map(|language| format!("{} ({})", language.code, language.name))
+				.collect();
+			println!("Bad language, use one of: {}", codes.join(", "));
+			std::process::exit(1);
+		}
+	});
+
+	// The --rules files and their language: "fr:rules.txt", or the --lang one
+	let mut rules_args: Vec<(&str, &str)> = Vec::new();
+	for value in matches.values_of("rules").into_iter().flatten() {
+		let scoped = value.find(':')
+			.and_then(|index| rules::find_language(&value[..index]).map(|language| (language.code, &value[index + 1..])));
+		match (scoped, fixed_language) {
+			(Some(scoped), _) => rules_args.push(scoped),
+			(None, Some(language)) => rules_args.push((language.code, value)),
+			(None, None) => {
+				let err = Error { message: format!("--rules {} needs a language: use --lang, or --rules fr:{}", value, value),
+					kind: clap::ErrorKind::InvalidValue,
+					info: None};
+				err.exit();
+			}
+		}
 	}
 
+	// Rules of each language that may be used: the built-in rules, then the
+	// user rules, then the --rules files
+	let mut rule_sets: Vec<(&str, rules::RuleSet)> = Vec::new();
+	for language in rules::LANGUAGES.iter() {
+		if fixed_language.is_some_and(|fixed| fixed.code != language.code) {
+			continue;
+		}
+		let mut rule_set = rules::RuleSet::builtin(language.code);
+		let mut rule_paths: Vec<String> = Vec::new();
+		if let Some(user_path) = rules::user_rule_path(language.code) {
+			if user_path.is_file() {
+				rule_paths.push(user_path.to_string_lossy().into_owned());
+			}
+		}
+		rule_paths.extend(rules_args.iter()
+			.filter(|&&(code, _)| code == language.code)
+			.map(|&(_, path)| path.to_string()));
+		for rule_path in rule_paths.iter() {
+			if let Err(err) = rule_set.add_from_file(rule_path) {
+				println!("{}", err);
+				std::process::exit(1);
+			}
+		}
+		rule_sets.push((language.code, rule_set));
+	}
+
+	let dictionary = matches.value_of("dict").map(|dic_path| {
+		let mut dictionary = match spell::Dictionary::load(dic_path) {
+			Ok(dictionary) => dictionary,
+			Err(err) => {
+				println!("{}", err);
+				std::process::exit(1);
+			}
+		};
+		if let Some(words_path) = matches.value_of("words") {
+			match std::fs::read_to_string(words_path) {
+				Ok(content) => dictionary.add_words(&content),
+				Err(err) => {
+					println!("{}: {}", words_path, err);
+					std::process::exit(1);
+				}
+			}
+		}
+		dictionary
+	});
+	let spell_fix = matches.is_present("spell-fix");
+
+	let hi_options = if matches.is_present("strip-hi") {
+		let brackets = matches.value_of("hi-brackets").unwrap_or(hi::DEFAULT_BRACKETS);
+		let label = matches.value_of("hi-label").unwrap_or(hi::DEFAULT_LABEL);
+		match hi::HiOptions::new(brackets, label) {
+			Ok(hi_options) => Some(hi_options),
+			Err(err) => {
+				let err = Error { message: format!("--strip-hi invalid argument: {}", err),
+					kind: clap::ErrorKind::InvalidValue,
+					info: None };
+				err.exit();
+			}
+		}
+	}
+	else {
+		None
+	};
+
+	// Rules of a file: the rules of its language, plus the accent fixes of
+	// the spell check with --spell-fix
+	let file_rule_set = |code: &str, sub_file: &srt::SubtitleFile| -> (rules::RuleSet, Vec<(String, String)>) {
+		let mut rule_set = rule_sets.iter().find(|&&(other, _)| other == code).unwrap().1.clone();
+		let fixes = match dictionary {
+			Some(ref dictionary) if spell_fix => spell::add_accent_fixes(&mut rule_set, sub_file, dictionary),
+			_ => Vec::new()
+		};
+		(rule_set, fixes)
+	};
+
 	// Additional check. Is there a way to do it with clap?
-	if out_file_path.is_some() && in_file_paths.len() > 1 {
+	let has_dir = in_paths.iter().any(|path| Path::new(path).is_dir());
+	if out_file_path.is_some() && (in_paths.len() > 1 || has_dir) {
 		let err = Error { message: "--out works only with single input".into(),
 			kind: clap::ErrorKind::TooManyValues,
 			info: None};
 		err.exit();
 	}
 
-	/////////////////////////////////////////////////////////////////
-	for in_file_path in in_file_paths {
+	let found = walk::find_files(&in_paths, &include, &exclude);
+	let in_file_paths = found.files;
+	let walk_errors = found.errors;
+	// With --check, they are issues, not to break the JSON output
+	if !check {
+		for err in walk_errors.iter() {
+			println!("{}", err);
+		}
+	}
+
+	let sync_reference = sync_ref_path.map(|ref_path| {
+		match srt::load_subtitles(ref_path, None, fps, None, parse_mode) {
+			Ok(ref_file) => ref_file.subtitles,
+			Err(err) => {
+				println!("{}: {}", ref_path, load_error_message(&err));
+				std::process::exit(1);
+			}
+		}
+	});
+
+	// Lengths in frames depend on the file
+	let file_lengths = |sub_file: &srt::SubtitleFile| -> Option<(i32, i32)> {
+		let file_fps = sub_file.fps.or(fps);
+		match (min_gap.to_milli(file_fps), min_duration.to_milli(file_fps)) {
+			(Some(min_gap_ms), Some(min_duration_ms)) => Some((min_gap_ms, min_duration_ms)),
+			_ => None
+		}
+	};
+
+	// Fixes a file and saves it
+	let fix_file = |in_file_path: &str| -> Outcome {
 		print!("{} ... ", in_file_path);
 
-		let subtitles_res = srt::load_subtitles(&in_file_path);
+		let subtitles_res = srt::load_subtitles(in_file_path, format, fps, input_encoding, parse_mode);
 		match subtitles_res {
 			Ok(_) => (),
 			Err(ref err) => {
-				println!("{}", err);
-				std::process::exit(1);
+				println!("{}", load_error_message(err));
+				return Outcome::Failed;
 			}
 		}
 
-		let mut subtitles = subtitles_res.unwrap();
-		do_replacements(&mut subtitles, &language);
-		do_time_changes(&mut subtitles, time_shift_ms, time_stretch_ms);
+		let mut sub_file = subtitles_res.unwrap();
+		// To tell if the file changed
+		let original = std::fs::read(in_file_path).ok();
+		// Messages printed once the file is done
+		let mut report: Vec<String> = Vec::new();
+		for diagnostic in sub_file.diagnostics.iter() {
+			report.push(format!("parse: {}", diagnostic));
+		}
+		if input_encoding.is_none() {
+			report.push(format!("encoding: {}, {}", sub_file.text_format.encoding.name(), sub_file.encoding_reason));
+		}
+		let repaired = srt::repair_mojibake(&mut sub_file.subtitles);
+		if !repaired.is_empty() {
+			let nums: Vec<String> = repaired.iter().map(|num| format!("#{}", num)).collect();
+			report.push(format!("encoding: mis-decoded text repaired in {}", nums.join(", ")));
+		}
+		let code = match file_language(fixed_language, &sub_file.subtitles) {
+			Ok((code, detected)) => {
+				report.extend(detected);
+				code
+			},
+			Err(err) => {
+				println!("skipped");
+				for line in report.iter() {
+					println!("  {}", line);
+				}
+				println!("  {}", err);
+				return Outcome::Skipped;
+			}
+		};
+		if let Some(ref hi_options) = hi_options {
+			let (count, removed) = hi::strip_hi(&mut sub_file, hi_options);
+			report.push(format!("hi: {} annotation(s) removed", count));
+			if !removed.is_empty() {
+				let nums: Vec<String> = removed.iter().map(|num| format!("#{}", num)).collect();
+				report.push(format!("hi: subtitles left empty and removed: {}", nums.join(", ")));
+			}
+		}
+		let (rule_set, spell_fixes) = file_rule_set(code, &sub_file);
+		for (word, suggestion) in spell_fixes.iter() {
+			report.push(format!("spelling: '{}' -> '{}'", word, suggestion));
+		}
+		if let Some(ref dictionary) = dictionary {
+			for misspelling in spell::spell_check(&sub_file, dictionary, &rule_set) {
+				report.push(format!("spelling: {}", misspelling));
+			}
+		}
+		txt_rep::replace_subtitles(&mut sub_file.subtitles, &rule_set);
+		if wrap {
+			for num in wrap::wrap_subtitles(&mut sub_file.subtitles, max_chars, max_lines) {
+				report.push(format!("wrap: #{} does not fit in {} line(s) of {} characters", num, max_lines, max_chars));
+			}
+		}
+		if let (Some(fps_from), Some(fps_to)) = (fps_from, fps_to) {
+			fps::change_fps(&mut sub_file, fps_from, fps_to);
+		}
+		if let Some(ref anchors) = sync_anchors {
+			if let Err(err) = sync::sync_two_points(&mut sub_file.subtitles, anchors[0], anchors[1]) {
+				println!("{}", err);
+				return Outcome::Failed;
+			}
+		}
+		if let Some(ref reference) = sync_reference {
+			match sync::sync_to_reference(&mut sub_file.subtitles, reference) {
+				Ok(segments) => for segment in segments.iter() {
+					report.push(format!("sync: subtitles #{} to #{}: scale {:.5}, offset {}",
+						segment.first_num, segment.last_num, segment.linear.scale,
+						srt::format_srt_time_from_milli(segment.linear.offset.round() as i32)));
+				},
+				Err(err) => {
+					println!("{}", err);
+					return Outcome::Failed;
+				}
+			}
+		}
+		srt::shift_and_stretch(&mut sub_file.subtitles, time_shift_ms, time_stretch_ms);
+		// After the sync, whose anchors use the numbers of the file
+		if normalize {
+			for line in normalize::normalize(&mut sub_file.subtitles).describe() {
+				report.push(format!("normalize: {}", line));
+			}
+		}
+		// Lengths in frames need a frame rate only when used
+		if fix_timing || fix_cps {
+			let (min_gap_ms, min_duration_ms) = match file_lengths(&sub_file) {
+				Some(lengths) => lengths,
+				None => {
+					println!("Unknown frame rate, use --fps");
+					return Outcome::Failed;
+				}
+			};
+			if fix_timing {
+				for fix in repair::repair_timing(&mut sub_file.subtitles, min_gap_ms, min_duration_ms) {
+					report.push(format!("timing: {}", fix));
+				}
+			}
+			if fix_cps {
+				for fix in reading::extend_fast(&mut sub_file.subtitles, max_cps, min_gap_ms) {
+					let still = if fix.fits(max_cps) { "" } else { ", still too fast" };
+					report.push(format!("cps: {}{}", fix, still));
+				}
+			}
+		}
+		if cps_report {
+			for speed in reading::analyse(&sub_file.subtitles) {
+				let mark = if speed.cps > max_cps { " (too fast)" } else { "" };
+				report.push(format!("cps: {}{}", speed, mark));
+			}
+		}
 
 		// Do backup
 		if !no_backup {
@@ -169,13 +688,206 @@ fn main() {
 			out_file_path.unwrap()
 		};
 
-		match srt::save_subtitles(&subtitles, &final_out_file_path) {
-			Ok(_) => (),
-			Err(_) => {
+		// Converts when the output file has another known extension
+		if let Some(out_format) = srt::Format::from_path(final_out_file_path) {
+			if sub_file.fps.is_none() {
+				sub_file.fps = fps;
+			}
+			if out_format == srt::Format::MicroDvd && sub_file.fps.is_none() {
+				println!("Unknown frame rate, use --fps");
+				return Outcome::Failed;
+			}
+			sub_file.convert(out_format);
+		}
+
+		if let Some(encoding) = encoding {
+			sub_file.text_format.encoding = encoding;
+		}
+		if let Some(bom) = bom {
+			sub_file.text_format.bom = bom;
+		}
+		if let Some(eol) = eol {
+			sub_file.text_format.eol = eol;
+		}
+
+		match srt::save_subtitles(&sub_file, final_out_file_path) {
+			Ok(unmappable) => if unmappable > 0 {
+				report.push(format!("encoding: {} character(s) cannot be written in {}, replaced by '?'",
+					unmappable, sub_file.text_format.encoding.name()));
+			},
+			Err(err) => {
+				println!("{}", err);
 				println!("Save failed");
-				std::process::exit(1);
+				return Outcome::Failed;
+			}
+		}
+		println!("done: {} subtitles", sub_file.subtitles.len());
+		for line in report.iter() {
+			println!("  {}", line);
+		}
+		if std::fs::read(final_out_file_path).ok() == original {
+			Outcome::Unchanged
+		}
+		else {
+			Outcome::Changed
+		}
+	};
+
+	let mut issues: Vec<check::Issue> = walk_errors.iter().filter(|_| check).map(|err| check::Issue {
+		file: err.path.clone(),
+		num: 0,
+		line_num: 0,
+		kind: check::IssueKind::Walk,
+		message: err.error.to_string()
+	}).collect();
+	let mut summaries: BTreeMap<String, DirSummary> = BTreeMap::new();
+	let mut failed = !walk_errors.is_empty();
+
+	// Summaries are printed by directory
+	let dir_of = |file_path: &str| match Path::new(file_path).parent() {
+		Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy().into_owned(),
+		_ => ".".to_string()
+	};
+	if !check && !dry_run && !diff {
+		for skipped_path in found.skipped.iter() {
+			summaries.entry(dir_of(skipped_path)).or_default().skipped += 1;
+		}
+	}
+
+	/////////////////////////////////////////////////////////////////
+	for in_file_path in in_file_paths.iter().map(String::as_str) {
+		if check {
+			match srt::load_subtitles(in_file_path, format, fps, input_encoding, parse_mode) {
+				Ok(sub_file) => {
+					for diagnostic in sub_file.diagnostics.iter() {
+						issues.push(check::Issue {
+							file: in_file_path.to_string(),
+							num: 0,
+							line_num: diagnostic.error.line_num() as u32,
+							kind: check::IssueKind::Parse,
+							message: diagnostic.to_string()
+						});
+					}
+					// Without a trusted language, the file is not checked
+					let code = match file_language(fixed_language, &sub_file.subtitles) {
+						Ok((code, _)) => code,
+						Err(err) => {
+							issues.push(check::Issue {
+								file: in_file_path.to_string(),
+								num: 0,
+								line_num: 0,
+								kind: check::IssueKind::Language,
+								message: err
+							});
+							continue;
+						}
+					};
+					let (min_gap_ms, min_duration_ms) = match file_lengths(&sub_file) {
+						Some(lengths) => lengths,
+						None => {
+							issues.push(check::Issue {
+								file: in_file_path.to_string(),
+								num: 0,
+								line_num: 0,
+								kind: check::IssueKind::Parse,
+								message: "Unknown frame rate, use --fps".to_string()
+							});
+							continue;
+						}
+					};
+					let (rule_set, _) = file_rule_set(code, &sub_file);
+					let options = check::CheckOptions {
+						rule_set: &rule_set,
+						min_gap: min_gap_ms,
+						min_duration: min_duration_ms,
+						max_cps,
+						dictionary: dictionary.as_ref()
+					};
+					issues.extend(check::check_subtitles(in_file_path, &sub_file, &options));
+				},
+				Err(err) => issues.push(check::Issue {
+					file: in_file_path.to_string(),
+					num: 0,
+					line_num: 0,
+					kind: check::IssueKind::Parse,
+					message: load_error_message(&err)
+				})
+			}
+			continue;
+		}
+
+		if dry_run || diff {
+			match srt::load_subtitles(in_file_path, format, fps, input_encoding, parse_mode) {
+				Ok(sub_file) => {
+					if !diff {
+						for diagnostic in sub_file.diagnostics.iter() {
+							println!("{}: {}", in_file_path, diagnostic);
+						}
+					}
+					let code = match file_language(fixed_language, &sub_file.subtitles) {
+						Ok((code, detected)) => {
+							if let (Some(detected), false) = (detected, diff) {
+								println!("{}: {}", in_file_path, detected);
+							}
+							code
+						},
+						Err(err) => {
+							println!("{}: {}", in_file_path, err);
+							continue;
+						}
+					};
+					let (rule_set, _) = file_rule_set(code, &sub_file);
+					let changes = preview::text_changes(&sub_file, &rule_set);
+					if diff {
+						print!("{}", preview::format_diff(in_file_path, &changes));
+					}
+					else {
+						println!("{}: {} line(s) would change", in_file_path, changes.len());
+						print!("{}", preview::format_dry_run(&changes));
+					}
+				},
+				Err(err) => {
+					println!("{}: {}", in_file_path, load_error_message(&err));
+					failed = true;
+				}
 			}
+			continue;
 		}
-		println!("done: {} subtitles", subtitles.len());
+
+		let outcome = fix_file(in_file_path);
+		failed |= outcome == Outcome::Failed;
+		let summary = summaries.entry(dir_of(in_file_path)).or_default();
+		if outcome != Outcome::Skipped {
+			summary.processed += 1;
+		}
+		match outcome {
+			Outcome::Unchanged => (),
+			Outcome::Changed => summary.changed += 1,
+			Outcome::Skipped => summary.skipped += 1,
+			Outcome::Failed => summary.failed += 1
+		}
+	}
+	if has_dir || in_file_paths.len() > 1 {
+		for (dir, summary) in summaries.iter() {
+			println!("{}: {} processed, {} changed, {} skipped, {} failed",
+				dir, summary.processed, summary.changed, summary.skipped, summary.failed);
+		}
+	}
+	if check {
+		if json {
+			println!("{}", check::issues_to_json(&issues));
+		}
+		else {
+			for issue in issues.iter() {
+				println!("{}", issue);
+			}
+			println!("{} problem(s) found", issues.len());
+		}
+		if !issues.is_empty() {
+			std::process::exit(1);
+		}
+	}
+	if failed {
+		std::process::exit(1);
 	}
 }
@@ -0,0 +1,171 @@
+use fps::Fps;
+use srt::{Format, Subtitle, SubtitleFile};
+use error::ParseError;
+
+// Parses a "{123}" frame number at the start of the text, returns the frame
+// (None if the braces are empty) and the rest of the text
+fn parse_frame(text: &str) -> Option<(Option<i64>, &str)> {
+	if !text.starts_with('{') {
+		return None;
+	}
+	let close_index = text.find('}')?;
+	let frame_str = text[1..close_index].trim();
+	let frame = if frame_str.is_empty() {
+		None
+	}
+	else {
+		Some(frame_str.parse().ok()?)
+	};
+	Some((frame, &text[close_index + 1..]))
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Parses a MicroDVD file: one subtitle per line, "{start}{end}text|text"
+// with frame numbers. The frame rate comes from the fps parameter, or from
+// a first "{1}{1}23.976" subtitle. An empty end frame means the subtitle
+// lasts until the next one.
+pub fn parse_microdvd(content: &str, fps: Option<Fps>) -> Result<SubtitleFile,ParseError> {
+	let mut file = SubtitleFile::new(Format::MicroDvd);
+	file.fps = fps;
+
+	// Frames as read, with their line, converted once the frame rate is known
+	let mut frames: Vec<(i64, Option<i64>, usize, &str)> = Vec::new();
+	let mut num = 1;
+
+	for (line_index, line_ori) in content.lines().enumerate() {
+		let line_num = line_index + 1;
+		let line = line_ori.trim();
+		if line.is_empty() {
+			continue;
+		}
+
+		let (from, to, text) = match parse_frame(line).and_then(|(from, rest)| {
+			parse_frame(rest).map(|(to, text)| (from, to, text))
+		}) {
+			Some((Some(from), to, text)) => (from, to, text),
+			_ => {
+				return Err(ParseError::BadFrames { line_num, text: line.to_string() });
+			}
+		};
+
+		// Frame rate header
+		if frames.is_empty() && file.header.is_empty() && from == 1 && to == Some(1) {
+			if let Some(header_fps) = Fps::parse(text) {
+				if file.fps.is_none() {
+					file.fps = Some(header_fps);
+				}
+				file.header.push(line.to_string());
+				continue;
+			}
+		}
+
+		let mut subtitle = Subtitle { num, line_num: line_num as u32, ..Default::default() };
+		for text in text.split('|') {
+			subtitle.push_text(text);
+		}
+		file.subtitles.push(subtitle);
+		frames.push((from, to, line_num, line));
+		num += 1;
+	}
+
+	let fps = match file.fps {
+		Some(fps) => fps,
+		None => {
+			return Err(ParseError::UnknownFrameRate);
+		}
+	};
+	for index in 0..frames.len() {
+		let (from, to, line_num, line) = frames[index];
+		let to = match to {
+			Some(to) => to,
+			None => match frames.get(index + 1) {
+				Some(&(next_from, _, _, _)) => next_from,
+				None => from.saturating_add(fps.milli_to_frame(2000))
+			}
+		};
+		match (fps.frame_to_milli(from), fps.frame_to_milli(to)) {
+			(Some(time_from), Some(time_to)) => {
+				file.subtitles[index].time_from = time_from;
+				file.subtitles[index].time_to = time_to;
+			},
+			_ => {
+				return Err(ParseError::BadFrames { line_num, text: line.to_string() });
+			}
+		}
+	}
+	Ok(file)
+}
+
+#[test]
+fn test_parse_microdvd() {
+	let sub = "{1}{1}25\n\
+{25}{50}Hello|{y:i}mister\n\
+\n\
+{100}{}Hi\n\
+{150}{175}Bye\n";
+	let file_res = parse_microdvd(sub, None);
+	assert!(file_res.is_ok(), "{}", file_res.err().unwrap());
+	let file = file_res.unwrap();
+	assert_eq!(file.fps, Some(Fps { num: 25, den: 1 }));
+	assert_eq!(file.header, vec!["{1}{1}25"]);
+	assert_eq!(file.subtitles.len(), 3);
+	assert_eq!(file.subtitles[0].time_from, 1_000);
+	assert_eq!(file.subtitles[0].time_to, 2_000);
+	assert_eq!(file.subtitles[0].texts.len(), 2);
+	assert_eq!(file.subtitles[0].texts[1], "{y:i}mister");
+	assert_eq!(file.subtitles[1].num, 2);
+	assert_eq!(file.subtitles[1].time_to, 6_000);
+
+	// The given frame rate wins over the header
+	let file = parse_microdvd(sub, Fps::parse("50")).unwrap();
+	assert_eq!(file.subtitles[0].time_from, 500);
+
+	assert!(parse_microdvd("{25}{50}Hello\n", None).is_err());
+	assert!(parse_microdvd("{25}{50}Hello\n", Fps::parse("25")).is_ok());
+	assert!(parse_microdvd("{25}Hello\n", Fps::parse("25")).is_err());
+	match parse_microdvd("{25}{50}Hello\n{99999999999999}{}Bye\n", Fps::parse("25")) {
+		Err(ParseError::BadFrames { line_num, .. }) => assert_eq!(line_num, 2),
+		_ => panic!("bad frames expected")
+	}
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Uses the frame rate of the file, 25 fps if unknown
+pub fn format_microdvd(file: &SubtitleFile) -> String {
+	let fps = file.fps.unwrap_or(Fps { num: 25, den: 1 });
+	let mut ret = String::new();
+
+	for line in file.header.iter() {
+		ret.push_str(line);
+		ret.push_str("\r\n");
+	}
+
+	for subtitle in file.subtitles.iter() {
+		ret.push_str(&format!("{{{}}}{{{}}}",
+			fps.milli_to_frame(subtitle.time_from),
+			fps.milli_to_frame(subtitle.time_to)));
+		ret.push_str(&subtitle.texts.join("|"));
+		ret.push_str("\r\n");
+	}
+	ret
+}
+
+#[test]
+fn test_format_microdvd() {
+	let sub = "{1}{1}23.976\r\n\
+{24}{48}Hello|{y:i}mister\r\n\
+{100}{150}Hi\r\n";
+	let file = parse_microdvd(sub, None).unwrap();
+	assert_eq!(format_microdvd(&file), sub);
+
+	let mut file = parse_microdvd("{25}{50}Hello\n", Fps::parse("25")).unwrap();
+	assert_eq!(format_microdvd(&file), "{25}{50}Hello\r\n");
+	file.fps = Fps::parse("50");
+	assert_eq!(format_microdvd(&file), "{50}{100}Hello\r\n");
+
+	// From SRT
+	let mut file = ::srt::parse_subtitles("1\n00:00:01,000 --> 00:00:02,000\nhi\n", Format::Srt, None).unwrap();
+	file.fps = Fps::parse("23.976");
+	file.convert(Format::MicroDvd);
+	assert_eq!(format_microdvd(&file), "{1}{1}23.976\r\n{24}{48}hi\r\n");
+}
@@ -0,0 +1,124 @@
+use srt::Subtitle;
+
+// What was wrong with the order and numbering of the subtitles
+#[derive(Debug, Default, PartialEq)]
+pub struct Normalization {
+	// Numbers found on more than one subtitle
+	pub duplicate_nums: Vec<u32>,
+	// Subtitles starting before a previous one, by old number
+	pub moved: Vec<u32>,
+	// Subtitles identical to a previous one, removed, by old number
+	pub merged: Vec<u32>,
+	// How many subtitles got a new number
+	pub renumbered: usize
+}
+
+impl Normalization {
+	// One line per kind of problem found
+	pub fn describe(&self) -> Vec<String> {
+		let nums = |nums: &[u32]| -> String {
+			let nums: Vec<String> = nums.iter().map(|num| format!("#{}", num)).collect();
+			nums.join(", ")
+		};
+		let mut lines = Vec::new();
+		if !self.duplicate_nums.is_empty() {
+			lines.push(format!("duplicate numbers: {}", nums(&self.duplicate_nums)));
+		}
+		if !self.moved.is_empty() {
+			lines.push(format!("not in time order, moved: {}", nums(&self.moved)));
+		}
+		if !self.merged.is_empty() {
+			lines.push(format!("exact duplicates removed: {}", nums(&self.merged)));
+		}
+		if self.renumbered > 0 {
+			lines.push(format!("{} subtitle(s) renumbered", self.renumbered));
+		}
+		lines
+	}
+}
+
+// Same times, text and settings
+fn same_subtitle(a: &Subtitle, b: &Subtitle) -> bool {
+	a.time_from == b.time_from && a.time_to == b.time_to && a.texts == b.texts
+	 && a.ident == b.ident && a.settings == b.settings
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Sorts the subtitles by start time, keeping the order of the subtitles
+// that start at the same time, removes the exact duplicates and numbers the
+// subtitles from 1.
+pub fn normalize(subtitles: &mut Vec<Subtitle>) -> Normalization {
+	let mut ret = Normalization::default();
+
+	let mut nums: Vec<u32> = subtitles.iter().map(|subtitle| subtitle.num).collect();
+	nums.sort_unstable();
+	for pair in nums.windows(2) {
+		if pair[0] == pair[1] && ret.duplicate_nums.last() != Some(&pair[0]) {
+			ret.duplicate_nums.push(pair[0]);
+		}
+	}
+
+	let mut latest_from = i32::MIN;
+	for subtitle in subtitles.iter() {
+		if subtitle.time_from < latest_from {
+			ret.moved.push(subtitle.num);
+		}
+		latest_from = latest_from.max(subtitle.time_from);
+	}
+
+	// Stable
+	subtitles.sort_by_key(|subtitle| subtitle.time_from);
+
+	let mut kept: Vec<Subtitle> = Vec::new();
+	for mut subtitle in subtitles.drain(..) {
+		let same = kept.iter_mut().rev()
+			.take_while(|previous| previous.time_from == subtitle.time_from)
+			.find(|previous| same_subtitle(previous, &subtitle));
+		match same {
+			Some(previous) => {
+				ret.merged.push(subtitle.num);
+				previous.notes.append(&mut subtitle.notes);
+			},
+			None => kept.push(subtitle)
+		}
+	}
+	*subtitles = kept;
+
+	for (index, subtitle) in subtitles.iter_mut().enumerate() {
+		let num = index as u32 + 1;
+		if subtitle.num != num {
+			subtitle.num = num;
+			ret.renumbered += 1;
+		}
+	}
+	ret
+}
+
+#[test]
+fn test_normalize() {
+	let srt = "1\n00:00:01,000 --> 00:00:02,000\nfirst\n\n\
+3\n00:00:05,000 --> 00:00:06,000\nthird\n\n\
+3\n00:00:03,000 --> 00:00:04,000\nsecond\n\n\
+4\n00:00:05,000 --> 00:00:06,000\nsame start\n\n\
+5\n00:00:05,000 --> 00:00:06,000\nthird\n\n\
+7\n00:00:07,000 --> 00:00:08,000\nlast\n";
+	let mut subs = ::srt::parse_srt(srt).unwrap();
+	let normalization = normalize(&mut subs);
+	assert_eq!(normalization.duplicate_nums, vec![3]);
+	assert_eq!(normalization.moved, vec![3]);
+	assert_eq!(normalization.merged, vec![5]);
+	assert_eq!(normalization.renumbered, 2);
+
+	let texts: Vec<&str> = subs.iter().map(|sub| sub.texts[0].as_str()).collect();
+	assert_eq!(texts, vec!["first", "second", "third", "same start", "last"]);
+	let nums: Vec<u32> = subs.iter().map(|sub| sub.num).collect();
+	assert_eq!(nums, vec![1, 2, 3, 4, 5]);
+	assert_eq!(normalization.describe(), vec![
+		"duplicate numbers: #3",
+		"not in time order, moved: #3",
+		"exact duplicates removed: #5",
+		"2 subtitle(s) renumbered"]);
+
+	// Nothing to do
+	assert_eq!(normalize(&mut subs), Normalization::default());
+}
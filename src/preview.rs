@@ -0,0 +1,160 @@
+use srt;
+use srt::{Format, Subtitle, SubtitleFile};
+use txt_rep;
+use rules::RuleSet;
+
+// A line of text that the replacement rules change
+#[derive(Debug)]
+pub struct LineChange {
+	pub num: u32,
+	pub time_from: i32,
+	// 0 when unknown
+	pub line_num: u32,
+	pub before: String,
+	pub after: String,
+	// The whole lines of the file, with the times and fields of the formats
+	// that have all the text of a subtitle on one line
+	pub before_line: String,
+	pub after_line: String,
+	pub rules: Vec<txt_rep::FiredRule>
+}
+
+impl LineChange {
+	// The rules that fired, like: 'Ca' -> 'Ça', 'oe*' -> 'œ'
+	pub fn rules_to_string(&self) -> String {
+		let rules: Vec<String> = self.rules.iter()
+			.map(|(what, with)| format!("'{}' -> '{}'", what, with))
+			.collect();
+		rules.join(", ")
+	}
+}
+
+// The line of the file that has all the text of a subtitle (ASS, MicroDVD),
+// written with the given texts
+fn file_line(sub_file: &SubtitleFile, subtitle: &Subtitle, texts: Vec<String>) -> String {
+	let mut one_file = SubtitleFile::new(sub_file.format);
+	one_file.header = sub_file.header.clone();
+	one_file.fps = sub_file.fps;
+	one_file.subtitles.push(Subtitle {
+		texts,
+		notes: Vec::new(),
+		..subtitle.clone()
+	});
+	let content = srt::format_subtitles(&one_file);
+	content.lines().last().unwrap_or("").to_string()
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Lists the lines of text that the replacement rules would change, without
+// changing them
+pub fn text_changes(sub_file: &SubtitleFile, rule_set: &RuleSet) -> Vec<LineChange> {
+	let mut changes: Vec<LineChange> = Vec::new();
+
+	// All the text of a subtitle is on one line in some formats
+	let one_line = match sub_file.format {
+		Format::Ass | Format::MicroDvd => true,
+		Format::Srt | Format::Vtt => false
+	};
+
+	for subtitle in sub_file.subtitles.iter() {
+		let new_texts = txt_rep::replace_texts_traced(&subtitle.texts, rule_set);
+		let (before_line, after_line) = if one_line {
+			let after_texts = new_texts.iter().map(|(new_text, _)| new_text.clone()).collect();
+			(file_line(sub_file, subtitle, subtitle.texts.clone()), file_line(sub_file, subtitle, after_texts))
+		}
+		else {
+			(String::new(), String::new())
+		};
+		for (text_index, (new_text, rules)) in new_texts.into_iter().enumerate() {
+			let text = &subtitle.texts[text_index];
+			if new_text != *text {
+				let line_num = if subtitle.line_num == 0 || one_line {
+					subtitle.line_num
+				}
+				else {
+					subtitle.line_num + text_index as u32
+				};
+				changes.push(LineChange {
+					num: subtitle.num,
+					time_from: subtitle.time_from,
+					line_num,
+					before: text.clone(),
+					before_line: if one_line { before_line.clone() } else { text.clone() },
+					after_line: if one_line { after_line.clone() } else { new_text.clone() },
+					after: new_text,
+					rules
+				});
+			}
+		}
+	}
+	changes
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// One change per line, for --dry-run
+pub fn format_dry_run(changes: &[LineChange]) -> String {
+	let mut ret = String::new();
+	for change in changes.iter() {
+		ret.push_str(&format!("#{} {}: '{}' -> '{}' ({})\n",
+			change.num, srt::format_srt_time_from_milli(change.time_from),
+			change.before, change.after, change.rules_to_string()));
+	}
+	ret
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unified diff like output, for --diff
+pub fn format_diff(file_path: &str, changes: &[LineChange]) -> String {
+	if changes.is_empty() {
+		return String::new();
+	}
+	let mut ret = format!("--- {}\n+++ {}\n", file_path, file_path);
+	let mut index = 0;
+	while index < changes.len() {
+		let change = &changes[index];
+		// The changes of a line that has all the text of a subtitle make one hunk
+		let mut rules = vec![change.rules_to_string()];
+		index += 1;
+		while index < changes.len() && changes[index].line_num == change.line_num
+			&& changes[index].before_line == change.before_line {
+			rules.push(changes[index].rules_to_string());
+			index += 1;
+		}
+		ret.push_str(&format!("@@ -{0},1 +{0},1 @@ #{1} {2} {3}\n-{4}\n+{5}\n",
+			change.line_num, change.num, srt::format_srt_time_from_milli(change.time_from),
+			rules.join(", "), change.before_line, change.after_line));
+	}
+	ret
+}
+
+#[test]
+fn test_text_changes() {
+	let srt = "1\n00:00:01,000 --> 00:00:02,000\nFine\nCa va\n\n\
+2\n00:00:03,000 --> 00:00:04,000\nFine\n";
+	let sub_file = srt::parse_subtitles(srt, Format::Srt, None).unwrap();
+	let changes = text_changes(&sub_file, &RuleSet::builtin("fr"));
+	assert_eq!(changes.len(), 1);
+	assert_eq!(changes[0].line_num, 4);
+	assert_eq!(format_dry_run(&changes), "#1 00:00:01,000: 'Ca va' -> 'Ça va' ('Ca' -> 'Ça')\n");
+	assert_eq!(format_diff("a.srt", &changes), "--- a.srt\n+++ a.srt\n\
+@@ -4,1 +4,1 @@ #1 00:00:01,000 'Ca' -> 'Ça'\n-Ca va\n+Ça va\n");
+	assert_eq!(format_diff("a.srt", &[]), "");
+
+	// The whole line of the file in the diff
+	let ass = "[Events]\n\
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Fine\\NCa va\n\
+Dialogue: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,Ca\\NCa\n";
+	let sub_file = srt::parse_subtitles(ass, Format::Ass, None).unwrap();
+	let changes = text_changes(&sub_file, &RuleSet::builtin("fr"));
+	assert_eq!(changes.len(), 3);
+	assert_eq!(changes[0].line_num, 3);
+	assert_eq!(changes[0].before, "Ca va");
+	assert_eq!(format_diff("a.ass", &changes), "--- a.ass\n+++ a.ass\n\
+@@ -3,1 +3,1 @@ #1 00:00:01,000 'Ca' -> 'Ça'\n\
+-Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Fine\\NCa va\n\
++Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Fine\\NÇa va\n\
+@@ -4,1 +4,1 @@ #2 00:00:03,000 'Ca' -> 'Ça', 'Ca' -> 'Ça'\n\
+-Dialogue: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,Ca\\NCa\n\
++Dialogue: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,Ça\\NÇa\n");
+}
@@ -0,0 +1,191 @@
+use std::fmt;
+
+use srt;
+use srt::Subtitle;
+
+// Removes formatting tags: <i>, </font>, {\an8}... A '<' or '{' that is
+// not closed before the next one is text.
+pub fn strip_tags(text: &str) -> String {
+	let mut ret = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(c) = rest.chars().next() {
+		let close = match c {
+			'<' => Some('>'),
+			'{' => Some('}'),
+			_ => None
+		};
+		let tag_end = close.and_then(|close| rest.find(close))
+			.filter(|&stop| !rest[1..stop].contains(c));
+		match tag_end {
+			Some(stop) => rest = &rest[stop + 1..],
+			None => {
+				ret.push(c);
+				rest = &rest[c.len_utf8()..];
+			}
+		}
+	}
+	ret
+}
+
+#[test]
+fn test_strip_tags() {
+	assert_eq!(strip_tags("<i>Hello</i> {\\an8}you"), "Hello you");
+	assert_eq!(strip_tags("<font color=\"red\">a</font>b"), "ab");
+	assert_eq!(strip_tags("a < b"), "a < b");
+	assert_eq!(strip_tags("a < b <i>c</i>"), "a < b c");
+}
+
+// Reading speed of a subtitle
+#[derive(Debug)]
+pub struct CueSpeed {
+	pub num: u32,
+	pub time_from: i32,
+	pub duration: i32,
+	// Characters shown, without tags and line breaks
+	pub chars: usize,
+	pub words: usize,
+	// Characters per second and words per minute
+	pub cps: f64,
+	pub wpm: f64
+}
+
+impl fmt::Display for CueSpeed {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "#{} {} {:.3}s {} chars {} words {:.1} cps {:.0} wpm",
+			self.num, srt::format_srt_time_from_milli(self.time_from),
+			self.duration as f64 / 1000.0, self.chars, self.words, self.cps, self.wpm)
+	}
+}
+
+pub fn cue_speed(subtitle: &Subtitle) -> CueSpeed {
+	let mut chars = 0;
+	let mut words = 0;
+	for text in subtitle.texts.iter() {
+		let text = strip_tags(text);
+		chars += text.trim().chars().count();
+		words += text.split_whitespace().count();
+	}
+
+	let duration = subtitle.time_to - subtitle.time_from;
+	let (cps, wpm) = if duration > 0 {
+		let seconds = duration as f64 / 1000.0;
+		(chars as f64 / seconds, words as f64 * 60.0 / seconds)
+	}
+	else if chars > 0 {
+		(f64::INFINITY, f64::INFINITY)
+	}
+	else {
+		(0.0, 0.0)
+	};
+	CueSpeed {
+		num: subtitle.num,
+		time_from: subtitle.time_from,
+		duration,
+		chars,
+		words,
+		cps,
+		wpm
+	}
+}
+
+///////////////////////////////////////////////////////////////////////////////
+pub fn analyse(subtitles: &[Subtitle]) -> Vec<CueSpeed> {
+	subtitles.iter().map(cue_speed).collect()
+}
+
+#[test]
+fn test_analyse() {
+	let srt = "1\n00:00:01,000 --> 00:00:03,000\n<i>Hello,</i>\nhow are you?\n\n\
+2\n00:00:04,000 --> 00:00:04,000\nToo fast\n";
+	let subs = srt::parse_srt(srt).unwrap();
+	let speeds = analyse(&subs);
+	assert_eq!(speeds[0].chars, 18);
+	assert_eq!(speeds[0].words, 4);
+	assert_eq!(speeds[0].duration, 2000);
+	assert_eq!(speeds[0].cps, 9.0);
+	assert_eq!(speeds[0].wpm, 120.0);
+	assert_eq!(speeds[0].to_string(), "#1 00:00:01,000 2.000s 18 chars 4 words 9.0 cps 120 wpm");
+	assert!(speeds[1].cps.is_infinite());
+}
+
+// A subtitle made longer to be read
+#[derive(Debug)]
+pub struct SpeedFix {
+	pub num: u32,
+	pub cps_before: f64,
+	pub cps_after: f64,
+	pub time_to_before: i32,
+	pub time_to_after: i32
+}
+
+impl SpeedFix {
+	// False if there was not enough room before the next subtitle
+	pub fn fits(&self, max_cps: f64) -> bool {
+		self.cps_after <= max_cps
+	}
+}
+
+impl fmt::Display for SpeedFix {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "#{} {:.1} cps -> {:.1} cps, end {} -> {}",
+			self.num, self.cps_before, self.cps_after,
+			srt::format_srt_time_from_milli(self.time_to_before),
+			srt::format_srt_time_from_milli(self.time_to_after))
+	}
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Extends the subtitles read faster than max_cps, into the free time before
+// the next subtitle, keeping min_gap. Returns every subtitle that was too
+// fast, extended or not.
+pub fn extend_fast(subtitles: &mut [Subtitle], max_cps: f64, min_gap: i32) -> Vec<SpeedFix> {
+	let mut fixes: Vec<SpeedFix> = Vec::new();
+
+	let mut order: Vec<usize> = (0..subtitles.len()).collect();
+	order.sort_by_key(|&index| subtitles[index].time_from);
+
+	for pos in 0..order.len() {
+		let index = order[pos];
+		let speed = cue_speed(&subtitles[index]);
+		if speed.cps <= max_cps {
+			continue;
+		}
+
+		let needed = (speed.chars as f64 * 1000.0 / max_cps).ceil() as i32;
+		let mut time_to = subtitles[index].time_from + needed;
+		if let Some(&next_index) = order.get(pos + 1) {
+			let limit = subtitles[next_index].time_from - min_gap;
+			if time_to > limit {
+				time_to = limit;
+			}
+		}
+
+		let time_to_before = subtitles[index].time_to;
+		if time_to > time_to_before {
+			subtitles[index].time_to = time_to;
+		}
+		fixes.push(SpeedFix {
+			num: speed.num,
+			cps_before: speed.cps,
+			cps_after: cue_speed(&subtitles[index]).cps,
+			time_to_before,
+			time_to_after: subtitles[index].time_to
+		});
+	}
+	fixes
+}
+
+#[test]
+fn test_extend_fast() {
+	let srt = "1\n00:00:01,000 --> 00:00:02,000\n34 characters, that is too fast!!!\n\n\
+2\n00:00:10,000 --> 00:00:11,000\n34 characters, that is too fast!!!\n\n\
+3\n00:00:11,500 --> 00:00:15,000\nFine\n";
+	let mut subs = srt::parse_srt(srt).unwrap();
+	let fixes = extend_fast(&mut subs, 17.0, 80);
+	assert_eq!(fixes.len(), 2);
+	assert_eq!(subs[0].time_to, 3_000);
+	assert!(fixes[0].fits(17.0));
+	assert_eq!(subs[1].time_to, 11_420);
+	assert!(!fixes[1].fits(17.0));
+	assert_eq!(subs[2].time_to, 15_000);
+}
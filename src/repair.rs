@@ -0,0 +1,176 @@
+use std;
+use std::fmt;
+
+use fps::Fps;
+use srt;
+use srt::Subtitle;
+
+// A duration given in milliseconds ("0.5", "00:00:00,500") or in frames
+// ("2f")
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+	Milli(i32),
+	Frames(i64)
+}
+
+impl Length {
+	pub fn parse(length_str: &str) -> Option<Length> {
+		let length_str = length_str.trim();
+		if let Some(frames_str) = length_str.strip_suffix('f') {
+			return frames_str.parse().ok().map(Length::Frames);
+		}
+		srt::parse_srt_time(length_str).map(Length::Milli)
+	}
+
+	// Frames need a frame rate. Too many frames give the longest length.
+	pub fn to_milli(self, fps: Option<Fps>) -> Option<i32> {
+		match self {
+			Length::Milli(milli) => Some(milli),
+			Length::Frames(frames) => fps.map(|fps| {
+				fps.frame_to_milli(frames).unwrap_or(if frames < 0 { i32::MIN } else { i32::MAX })
+			})
+		}
+	}
+}
+
+#[test]
+fn test_length() {
+	assert_eq!(Length::parse("2f"), Some(Length::Frames(2)));
+	assert_eq!(Length::parse("0.5"), Some(Length::Milli(500)));
+	assert_eq!(Length::parse("xf"), None);
+	assert_eq!(Length::parse("2f").unwrap().to_milli(Fps::parse("25")), Some(80));
+	assert_eq!(Length::parse("2f").unwrap().to_milli(None), None);
+	assert_eq!(Length::parse("99999999999f").unwrap().to_milli(Fps::parse("25")), Some(i32::MAX));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Problem {
+	// Ends before it starts, or when it starts
+	BadDuration,
+	// Ends after the next one starts
+	Overlap,
+	// Ends too close to the next one
+	SmallGap
+}
+
+// A change made to the end time of a subtitle
+#[derive(Debug)]
+pub struct TimingFix {
+	pub num: u32,
+	pub problem: Problem,
+	pub time_to_before: i32,
+	pub time_to_after: i32
+}
+
+impl TimingFix {
+	// False when the subtitle could not be changed
+	pub fn is_fixed(&self) -> bool {
+		self.time_to_before != self.time_to_after
+	}
+
+	// What happened, without the subtitle number
+	pub fn describe(&self) -> String {
+		let what = match self.problem {
+			Problem::BadDuration => "bad duration",
+			Problem::Overlap => "overlaps the next one",
+			Problem::SmallGap => "too close to the next one"
+		};
+		if self.is_fixed() {
+			format!("{}, end {} -> {}", what,
+				srt::format_srt_time_from_milli(self.time_to_before),
+				srt::format_srt_time_from_milli(self.time_to_after))
+		}
+		else {
+			format!("{}, cannot be fixed", what)
+		}
+	}
+}
+
+impl fmt::Display for TimingFix {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "#{} {}", self.num, self.describe())
+	}
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Fixes subtitles that end before they start, overlap the next subtitle or
+// end less than min_gap before it. End times are extended to give at least
+// min_duration, or trimmed, without going below min_duration unless the
+// subtitle would overlap the next one. Returns every change.
+pub fn repair_timing(subtitles: &mut [Subtitle], min_gap: i32, min_duration: i32) -> Vec<TimingFix> {
+	let mut fixes: Vec<TimingFix> = Vec::new();
+
+	// Subtitles may not be sorted
+	let mut order: Vec<usize> = (0..subtitles.len()).collect();
+	order.sort_by_key(|&index| subtitles[index].time_from);
+
+	for pos in 0..order.len() {
+		let index = order[pos];
+		let time_from = subtitles[index].time_from;
+		let time_to_before = subtitles[index].time_to;
+		let mut time_to = time_to_before;
+		let mut problem = None;
+
+		if time_to <= time_from {
+			problem = Some(Problem::BadDuration);
+			time_to = time_from + min_duration;
+		}
+
+		if let Some(&next_index) = order.get(pos + 1) {
+			let next_from = subtitles[next_index].time_from;
+			if time_to > next_from - min_gap {
+				if problem.is_none() {
+					problem = Some(if time_to > next_from { Problem::Overlap } else { Problem::SmallGap });
+				}
+				time_to = next_from - min_gap;
+				if time_to < time_from + min_duration {
+					// Keep the subtitle readable, even without a gap
+					time_to = std::cmp::min(time_from + min_duration, next_from);
+				}
+				if time_to <= time_from {
+					// Both start at the same time
+					time_to = time_to_before;
+				}
+			}
+		}
+
+		if let Some(problem) = problem {
+			subtitles[index].time_to = time_to;
+			fixes.push(TimingFix {
+				num: subtitles[index].num,
+				problem,
+				time_to_before,
+				time_to_after: time_to
+			});
+		}
+	}
+	fixes
+}
+
+#[test]
+fn test_repair_timing() {
+	let srt = "1\n00:00:01,000 --> 00:00:03,500\nOverlap\n\n\
+2\n00:00:03,000 --> 00:00:04,960\nSmall gap\n\n\
+3\n00:00:05,000 --> 00:00:05,000\nNo duration\n\n\
+4\n00:00:05,500 --> 00:00:07,000\nOverlap, too short to trim\n\n\
+5\n00:00:06,000 --> 00:00:08,000\nSame start\n\n\
+6\n00:00:06,000 --> 00:00:08,000\nSame start\n\n\
+7\n00:00:20,000 --> 00:00:21,000\nFine\n";
+	let mut subs = srt::parse_srt(srt).unwrap();
+	let fixes = repair_timing(&mut subs, 80, 1000);
+	assert_eq!(fixes.len(), 5);
+
+	assert_eq!(fixes[0].problem, Problem::Overlap);
+	assert_eq!(subs[0].time_to, 2_920);
+	assert_eq!(fixes[1].problem, Problem::SmallGap);
+	assert_eq!(subs[1].time_to, 4_920);
+	// Extended, then trimmed but kept long enough, without overlap
+	assert_eq!(fixes[2].problem, Problem::BadDuration);
+	assert_eq!(subs[2].time_to, 5_500);
+	assert_eq!(subs[3].time_to, 6_000);
+	assert_eq!(fixes[4].num, 5);
+	assert!(!fixes[4].is_fixed());
+	assert_eq!(subs[4].time_to, 8_000);
+	assert_eq!(fixes[4].to_string(), "#5 overlaps the next one, cannot be fixed");
+	assert_eq!(fixes[0].to_string(), "#1 overlaps the next one, end 00:00:03,500 -> 00:00:02,920");
+}
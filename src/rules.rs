@@ -1,3 +1,7 @@
+use regex::Regex;
+
+use error::RuleError;
+
 pub const RULES_FR: &'static [(&'static str, &'static str)] = &[
 	// Trop d'espaces
 	("  ", " "),
@@ -14,66 +18,6 @@ pub const RULES_FR: &'static [(&'static str, &'static str)] = &[
 	("1ère",  "1ʳᵉ"),
 	("1ères",  "1ʳᵉˢ"),
 
-	("2ème",  "2ᵉ"),
-	("3ème",  "3ᵉ"),
-	("4ème",  "4ᵉ"),
-	("5ème",  "5ᵉ"),
-	("6ème",  "6ᵉ"),
-	("7ème",  "7ᵉ"),
-	("8ème",  "8ᵉ"),
-	("9ème",  "9ᵉ"),
-
-	("#0ème",  "0ᵉ"),
-	("#1ème",  "1ᵉ"),
-	("#2ème",  "2ᵉ"),
-	("#3ème",  "3ᵉ"),
-	("#4ème",  "4ᵉ"),
-	("#5ème",  "5ᵉ"),
-	("#6ème",  "6ᵉ"),
-	("#7ème",  "7ᵉ"),
-	("#8ème",  "8ᵉ"),
-	("#9ème",  "9ᵉ"),
-
-	("2e",  "2ᵉ"),
-	("3e",  "3ᵉ"),
-	("4e",  "4ᵉ"),
-	("5e",  "5ᵉ"),
-	("6e",  "6ᵉ"),
-	("7e",  "7ᵉ"),
-	("8e",  "8ᵉ"),
-	("9e",  "9ᵉ"),
-
-	("#0e",  "0ᵉ"),
-	("#1e",  "1ᵉ"),
-	("#2e",  "2ᵉ"),
-	("#3e",  "3ᵉ"),
-	("#4e",  "4ᵉ"),
-	("#5e",  "5ᵉ"),
-	("#6e",  "6ᵉ"),
-	("#7e",  "7ᵉ"),
-	("#8e",  "8ᵉ"),
-	("#9e",  "9ᵉ"),
-
-	("2è",  "2ᵉ"),
-	("3è",  "3ᵉ"),
-	("4è",  "4ᵉ"),
-	("5è",  "5ᵉ"),
-	("6è",  "6ᵉ"),
-	("7è",  "7ᵉ"),
-	("8è",  "8ᵉ"),
-	("9è",  "9ᵉ"),
-
-	("#0è",  "0ᵉ"),
-	("#1è",  "1ᵉ"),
-	("#2è",  "2ᵉ"),
-	("#3è",  "3ᵉ"),
-	("#4è",  "4ᵉ"),
-	("#5è",  "5ᵉ"),
-	("#6è",  "6ᵉ"),
-	("#7è",  "7ᵉ"),
-	("#8è",  "8ᵉ"),
-	("#9è",  "9ᵉ"),
-
 	// Cédille
 	("ca", "ça"),
 	("Ca", "Ça"),
@@ -229,6 +173,14 @@ pub const RULES_FR: &'static [(&'static str, &'static str)] = &[
 	("Je doit", "Je dois")
 ];
 
+// Rules with regular expressions, applied after the others. The text to
+// search is a regular expression, the replacement can refer to the groups
+// with ${1}, ${2}...
+pub const REGEX_RULES_FR: &[(&str, &str)] = &[
+	// Ordinaux : 2ème, 10e, 21è...
+	(r"\b([2-9]|\d{2,})(?:ème|e|è)\b", "${1}ᵉ")
+];
+
 pub const RULES_EN: &'static [(&'static str, &'static str)] = &[
 	// Trop d'espaces
 	("  ", " "),
@@ -243,3 +195,367 @@ pub const RULES_EN: &'static [(&'static str, &'static str)] = &[
 	("+\u{A0},", ","),
 	("+ ,",      ",")
 ];
+
+pub const REGEX_RULES_EN: &[(&str, &str)] = &[
+];
+
+pub const RULES_ES: &[(&str, &str)] = &[
+	// Trop d'espaces
+	("  ", " "),
+
+	// Pas d'espace avant la ponctuation
+	("+ !", "!"),
+	("+ ?", "?"),
+	("+ ,", ","),
+
+	// Accents
+	("Que tal", "Qué tal"),
+	("Como estas", "Cómo estás"),
+	("Esta bien", "Está bien"),
+	("esta bien", "está bien"),
+	("tambien", "también"),
+	("Tambien", "También"),
+	("Adios", "Adiós"),
+	("adios", "adiós")
+];
+
+pub const REGEX_RULES_ES: &[(&str, &str)] = &[
+];
+
+pub const RULES_DE: &[(&str, &str)] = &[
+	// Trop d'espaces
+	("  ", " "),
+
+	// Pas d'espace avant la ponctuation
+	("+ !", "!"),
+	("+ ?", "?"),
+	("+ ,", ","),
+
+	// Abréviations
+	("z.B.", "z. B."),
+	("d.h.", "d. h."),
+	("u.a.", "u. a.")
+];
+
+pub const REGEX_RULES_DE: &[(&str, &str)] = &[
+	// Point de « usw »
+	(r"\busw\b([^.]|$)", "usw.${1}"),
+	// Guillemets allemands
+	(r#""([^"]*)""#, "„${1}“"),
+	(r"“([^“”]*)”", "„${1}“")
+];
+
+pub const RULES_IT: &[(&str, &str)] = &[
+	// Trop d'espaces
+	("  ", " "),
+
+	// Pas d'espace avant la ponctuation
+	("+ !", "!"),
+	("+ ?", "?"),
+	("+ ,", ","),
+
+	// Apostrophe au lieu d'accent
+	("E'", "È"),
+	("e'", "è"),
+
+	// Accent grave au lieu d'aigu
+	("perchè", "perché"),
+	("Perchè", "Perché"),
+	("poichè", "poiché"),
+	("finchè", "finché"),
+
+	// Accents manquants
+	("perche", "perché"),
+	("Perche", "Perché"),
+	("poiche", "poiché"),
+	("piu", "più"),
+	("Piu", "Più"),
+	("gia", "già"),
+	("Gia", "Già"),
+	("puo", "può"),
+	("cosi", "così"),
+	("Cosi", "Così"),
+	("citta", "città")
+];
+
+pub const RULES_PT: &[(&str, &str)] = &[
+	// Trop d'espaces
+	("  ", " "),
+
+	// Pas d'espace avant la ponctuation
+	("+ !", "!"),
+	("+ ?", "?"),
+	("+ ,", ","),
+
+	// Accents manquants
+	("nao", "não"),
+	("Nao", "Não"),
+	("voce", "você"),
+	("Voce", "Você"),
+	("voces", "vocês"),
+	("tambem", "também"),
+	("Tambem", "Também"),
+	("entao", "então"),
+	("Entao", "Então"),
+	("porem", "porém"),
+	("alguem", "alguém"),
+	("ninguem", "ninguém"),
+	("Ninguem", "Ninguém")
+];
+
+pub const REGEX_RULES_PT: &[(&str, &str)] = &[
+	// Terminaisons -ção et -ções, seulement pour des mots connus : Falcão
+	// ou cão s'écrivent avec -cão
+	(concat!(r"\b((?i:a|aten|inten|men|informa|situa|explica|rela|condi|opera|solu|na|li|educa|popula|",
+		r"organiza|comunica|esta|ora|fun|posi|dire|prote|sele|elei|produ|aplica|obriga|emo|tradi|evolu|",
+		r"revolu|investiga|conversa|cora|avalia|prepara|preocupa|sensa|ocupa|rea|cria))cao\b"), "${1}ção"),
+	(concat!(r"\b((?i:a|aten|inten|men|informa|situa|explica|rela|condi|opera|solu|na|li|educa|popula|",
+		r"organiza|comunica|esta|ora|fun|posi|dire|prote|sele|elei|produ|aplica|obriga|emo|tradi|evolu|",
+		r"revolu|investiga|conversa|cora|avalia|prepara|preocupa|sensa|ocupa|rea|cria))coes\b"), "${1}ções")
+];
+
+// A language known by fixsrt, with its built-in rules
+pub struct Language {
+	pub code: &'static str,
+	pub name: &'static str,
+	pub rules: &'static [(&'static str, &'static str)],
+	pub regex_rules: &'static [(&'static str, &'static str)],
+	// Questions and exclamations open with ¿ and ¡
+	pub inverted_marks: bool
+}
+
+pub const LANGUAGES: &[Language] = &[
+	Language { code: "fr", name: "french", rules: RULES_FR, regex_rules: REGEX_RULES_FR, inverted_marks: false },
+	Language { code: "en", name: "english", rules: RULES_EN, regex_rules: REGEX_RULES_EN, inverted_marks: false },
+	Language { code: "es", name: "spanish", rules: RULES_ES, regex_rules: REGEX_RULES_ES, inverted_marks: true },
+	Language { code: "de", name: "german", rules: RULES_DE, regex_rules: REGEX_RULES_DE, inverted_marks: false },
+	Language { code: "it", name: "italian", rules: RULES_IT, regex_rules: &[], inverted_marks: false },
+	Language { code: "pt", name: "portuguese", rules: RULES_PT, regex_rules: REGEX_RULES_PT, inverted_marks: false }
+];
+
+pub fn find_language(code: &str) -> Option<&'static Language> {
+	LANGUAGES.iter().find(|language| language.code == code)
+}
+
+// A replacement rule. With a regular expression, "with" can refer to the
+// groups of "what".
+#[derive(Debug, Clone)]
+pub struct Rule {
+	pub what: String,
+	pub with: String,
+	pub regex: Option<Regex>
+}
+
+// The rules used for a language: a built-in table, completed or overridden
+// by rule files
+#[derive(Debug, Clone)]
+pub struct RuleSet {
+	pub rules: Vec<Rule>,
+	// See Language
+	pub inverted_marks: bool
+}
+
+impl RuleSet {
+	// Unknown languages have no rules
+	pub fn builtin(code: &str) -> RuleSet {
+		let mut rule_set = RuleSet { rules: Vec::new(), inverted_marks: false };
+		let language = match find_language(code) {
+			Some(language) => language,
+			None => return rule_set
+		};
+		rule_set.inverted_marks = language.inverted_marks;
+		for &(what, with) in language.rules.iter() {
+			rule_set.add(what.to_string(), with.to_string());
+		}
+		for &(what, with) in language.regex_rules.iter() {
+			rule_set.add_regex(what.to_string(), with.to_string()).unwrap();
+		}
+		rule_set
+	}
+
+	// Replaces the rule of the same kind with the same "what", or adds the
+	// rule at the end
+	fn push(&mut self, rule: Rule) {
+		let existing = self.rules.iter_mut()
+			.find(|other| other.what == rule.what && other.regex.is_some() == rule.regex.is_some());
+		match existing {
+			Some(other) => other.with = rule.with,
+			None => self.rules.push(rule)
+		}
+	}
+
+	pub fn add(&mut self, what: String, with: String) {
+		self.push(Rule { what, with, regex: None });
+	}
+
+	pub fn add_regex(&mut self, what: String, with: String) -> Result<(), RuleError> {
+		let regex = Regex::new(&what).map_err(RuleError::Regex)?;
+		self.push(Rule { what, with, regex: Some(regex) });
+		Ok(())
+	}
+
+	///////////////////////////////////////////////////////////////////////////
+	// Adds the rules of a rule file. file_path is used for the errors.
+	pub fn add_from_str(&mut self, content: &str, file_path: &str) -> Result<(), RuleError> {
+		for (line_index, line) in content.lines().enumerate() {
+			let line_error = |err: RuleError| RuleError::At {
+				path: file_path.to_string(),
+				line_num: line_index + 1,
+				error: Box::new(err)
+			};
+			match parse_rule_line(line).map_err(line_error)? {
+				Some((false, what, with)) => self.add(what, with),
+				Some((true, what, with)) => self.add_regex(what, with).map_err(line_error)?,
+				None => ()
+			}
+		}
+		Ok(())
+	}
+
+	pub fn add_from_file(&mut self, file_path: &str) -> Result<(), RuleError> {
+		let content = ::std::fs::read_to_string(file_path)
+			.map_err(|error| RuleError::File { path: file_path.to_string(), error })?;
+		self.add_from_str(&content, file_path)
+	}
+}
+
+// Reads a quoted string at the start of line, returns it with the rest of
+// the line. Escapes: \" \\ \n \t \u{A0}
+// Raw strings, for regular expressions, only understand \" and keep the
+// other escapes as they are.
+fn parse_quoted(line: &str, raw: bool) -> Result<(String, &str), RuleError> {
+	let mut chars = line.char_indices();
+	match chars.next() {
+		Some((_, '"')) => (),
+		_ => return Err(RuleError::QuoteExpected)
+	}
+	let mut ret = String::new();
+	while let Some((index, c)) = chars.next() {
+		match c {
+			'"' => return Ok((ret, &line[index + 1..])),
+			'\\' if raw => match chars.next() {
+				Some((_, '"')) => ret.push('"'),
+				Some((_, c)) => {
+					ret.push('\\');
+					ret.push(c);
+				},
+				None => ret.push('\\')
+			},
+			'\\' => match chars.next() {
+				Some((_, '"')) => ret.push('"'),
+				Some((_, '\\')) => ret.push('\\'),
+				Some((_, 'n')) => ret.push('\n'),
+				Some((_, 't')) => ret.push('\t'),
+				Some((u_index, 'u')) => {
+					let rest = &line[u_index + 1..];
+					let close = match rest.find('}') {
+						Some(close) if rest.starts_with('{') => close,
+						_ => return Err(RuleError::BadUnicodeEscape)
+					};
+					let code = u32::from_str_radix(&rest[1..close], 16).ok()
+						.and_then(::std::char::from_u32)
+						.ok_or(RuleError::BadUnicodeEscape)?;
+					ret.push(code);
+					for _ in 0..close + 1 {
+						chars.next();
+					}
+				},
+				_ => return Err(RuleError::UnknownEscape)
+			},
+			c => ret.push(c)
+		}
+	}
+	Err(RuleError::MissingClosingQuote)
+}
+
+// Parses a line of a rule file, which is written like the built-in tables:
+//   ("Ca", "Ça"),
+// or more simply:
+//   "Ca" "Ça"
+// Regular expression rules start with "regex":
+//   regex("(\d+)ème\b", "${1}ᵉ"),
+// Empty lines and // comments are ignored. Returns (is_regex, what, with).
+fn parse_rule_line(line: &str) -> Result<Option<(bool, String, String)>, RuleError> {
+	let mut rest = line.trim();
+	if rest.is_empty() || rest.starts_with("//") {
+		return Ok(None);
+	}
+	let is_regex = rest.starts_with("regex");
+	if is_regex {
+		rest = rest[5..].trim_start();
+	}
+	let in_parens = rest.starts_with('(');
+	if in_parens {
+		rest = rest[1..].trim_start();
+	}
+	let (what, after_what) = parse_quoted(rest, is_regex)?;
+	rest = after_what.trim_start();
+	if let Some(after_comma) = rest.strip_prefix(',') {
+		rest = after_comma.trim_start();
+	}
+	let (with, after_with) = parse_quoted(rest, false)?;
+	rest = after_with.trim_start();
+	if in_parens {
+		rest = match rest.strip_prefix(')') {
+			Some(after_paren) => after_paren.trim_start(),
+			None => return Err(RuleError::ParenExpected)
+		};
+	}
+	if let Some(after_comma) = rest.strip_prefix(',') {
+		rest = after_comma.trim_start();
+	}
+	if !rest.is_empty() && !rest.starts_with("//") {
+		return Err(RuleError::UnexpectedText(rest.to_string()));
+	}
+
+	let bare = if is_regex {
+		&what
+	}
+	else {
+		what.trim_start_matches(['*', '+', '#']).trim_end_matches(['*', '+', '#'])
+	};
+	if bare.is_empty() {
+		return Err(RuleError::EmptyRule);
+	}
+	Ok(Some((is_regex, what, with)))
+}
+
+#[test]
+fn test_rule_set() {
+	let mut rule_set = RuleSet::builtin("fr");
+	let count = rule_set.rules.len();
+	let content = "// Comment\n\
+\n\
+(\"Ca\", \"Ça alors\"), // Overrides\n\
+\"+ok\" \"OK\"\n\
+\"a\\u{A0}b\" \"\\\"c\\\"\"\n\
+regex(\"(\\d+)h\\b\", \"${1} heures\")\n";
+	rule_set.add_from_str(content, "my.rules").unwrap();
+	assert_eq!(rule_set.rules.len(), count + 3);
+	assert!(rule_set.rules.iter().any(|rule| rule.what == "Ca" && rule.with == "Ça alors"));
+	assert_eq!((&rule_set.rules[count].what[..], &rule_set.rules[count].with[..]), ("+ok", "OK"));
+	assert_eq!(rule_set.rules[count + 1].what, "a\u{A0}b");
+	assert_eq!(rule_set.rules[count + 1].with, "\"c\"");
+	assert_eq!(rule_set.rules[count + 2].what, "(\\d+)h\\b");
+	assert!(rule_set.rules[count + 2].regex.is_some());
+
+	let mut add_error = |content: &str, file_path: &str| rule_set.add_from_str(content, file_path).unwrap_err().to_string();
+	assert_eq!(add_error("\"a\" \"b\"\n\"c\" d\n", "my.rules"), "my.rules:2: \" expected");
+	assert_eq!(add_error("(\"a\", \"b\"\n", "my.rules"), "my.rules:1: ) expected");
+	assert_eq!(add_error("\"*\" \"b\"", "x"), "x:1: empty rule");
+	assert_eq!(add_error("\"a\" \"b\" c", "x"), "x:1: unexpected text: c");
+	assert!(add_error("regex \"(a\" \"b\"", "x").starts_with("x:1: regex parse error"));
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// User rule file of a language, like ~/.config/fixsrt/rules_fr.txt. It may
+// not exist.
+pub fn user_rule_path(language: &str) -> Option<::std::path::PathBuf> {
+	let env_dir = |name: &str| ::std::env::var_os(name)
+		.filter(|dir| !dir.is_empty())
+		.map(::std::path::PathBuf::from);
+	let config_dir = env_dir("XDG_CONFIG_HOME")
+		.or_else(|| env_dir("HOME").map(|home| home.join(".config")))
+		.or_else(|| env_dir("APPDATA"))?;
+	Some(config_dir.join("fixsrt").join(format!("rules_{}.txt", language)))
+}
@@ -0,0 +1,567 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use charset;
+use srt::{Format, SubtitleFile};
+use regex;
+use rules::RuleSet;
+use txt_rep;
+use reading;
+use error::SpellError;
+
+// How flags are written in the .aff and .dic files
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FlagType {
+	// One character
+	Char,
+	// Two characters
+	Long,
+	// Numbers separated by commas
+	Num
+}
+
+fn parse_flags(flags_str: &str, flag_type: FlagType) -> Vec<u32> {
+	match flag_type {
+		FlagType::Char => flags_str.chars().map(|c| c as u32).collect(),
+		FlagType::Long => {
+			let chars: Vec<char> = flags_str.chars().collect();
+			chars.chunks(2).map(|pair| {
+				(pair[0] as u32) << 16 | pair.get(1).map_or(0, |&c| c as u32)
+			}).collect()
+		},
+		FlagType::Num => flags_str.split(',').filter_map(|num| num.trim().parse().ok()).collect()
+	}
+}
+
+// One character of an affix condition
+#[derive(Debug)]
+enum CondItem {
+	Any,
+	Char(char),
+	OneOf(Vec<char>),
+	NoneOf(Vec<char>)
+}
+
+impl CondItem {
+	fn matches(&self, c: char) -> bool {
+		match *self {
+			CondItem::Any => true,
+			CondItem::Char(item) => item == c,
+			CondItem::OneOf(ref items) => items.contains(&c),
+			CondItem::NoneOf(ref items) => !items.contains(&c)
+		}
+	}
+}
+
+// Parses a condition like "[^aeiou]y"
+fn parse_condition(cond_str: &str) -> Vec<CondItem> {
+	let mut items = Vec::new();
+	let mut chars = cond_str.chars();
+	while let Some(c) = chars.next() {
+		match c {
+			'.' => items.push(CondItem::Any),
+			'[' => {
+				let mut set: Vec<char> = Vec::new();
+				let mut negated = false;
+				for (index, c) in chars.by_ref().enumerate() {
+					match c {
+						']' => break,
+						'^' if index == 0 => negated = true,
+						c => set.push(c)
+					}
+				}
+				items.push(if negated { CondItem::NoneOf(set) } else { CondItem::OneOf(set) });
+			},
+			c => items.push(CondItem::Char(c))
+		}
+	}
+	items
+}
+
+// A prefix or a suffix rule
+#[derive(Debug)]
+struct Affix {
+	flag: u32,
+	cross_product: bool,
+	strip: String,
+	add: String,
+	condition: Vec<CondItem>
+}
+
+impl Affix {
+	// For a suffix, the condition is checked at the end of the word
+	fn condition_matches(&self, word: &str, is_suffix: bool) -> bool {
+		let chars: Vec<char> = word.chars().collect();
+		if chars.len() < self.condition.len() {
+			return false;
+		}
+		let start = if is_suffix { chars.len() - self.condition.len() } else { 0 };
+		self.condition.iter().zip(chars[start..].iter()).all(|(item, &c)| item.matches(c))
+	}
+
+	// The word without this suffix, if it can have it
+	fn remove_suffix(&self, word: &str) -> Option<String> {
+		let stem = word.strip_suffix(&self.add[..])?;
+		if stem.is_empty() && self.strip.is_empty() {
+			return None;
+		}
+		let base = format!("{}{}", stem, self.strip);
+		if self.condition_matches(&base, true) { Some(base) } else { None }
+	}
+
+	fn remove_prefix(&self, word: &str) -> Option<String> {
+		let stem = word.strip_prefix(&self.add[..])?;
+		if stem.is_empty() && self.strip.is_empty() {
+			return None;
+		}
+		let base = format!("{}{}", self.strip, stem);
+		if self.condition_matches(&base, false) { Some(base) } else { None }
+	}
+}
+
+// Words of a Hunspell dictionary, with its prefix and suffix rules
+#[derive(Debug)]
+pub struct Dictionary {
+	words: HashMap<String, Vec<u32>>,
+	prefixes: Vec<Affix>,
+	suffixes: Vec<Affix>,
+	// Names and other words of a project
+	extra_words: HashSet<String>
+}
+
+impl Dictionary {
+	///////////////////////////////////////////////////////////////////////////
+	// Reads the content of a .aff file and of a .dic file
+	pub fn parse(aff: &str, dic: &str) -> Result<Dictionary, SpellError> {
+		let mut flag_type = FlagType::Char;
+		let mut aliases: Vec<Vec<u32>> = Vec::new();
+		let mut prefixes: Vec<Affix> = Vec::new();
+		let mut suffixes: Vec<Affix> = Vec::new();
+		// Cross product of each affix flag
+		let mut cross: HashMap<(bool, String), bool> = HashMap::new();
+
+		for (line_index, line) in aff.lines().enumerate() {
+			let fields: Vec<&str> = line.split_whitespace().collect();
+			let error = |what: &'static str| SpellError::Affix { line_num: line_index + 1, what };
+			match fields.first() {
+				Some(&"FLAG") => {
+					flag_type = match fields.get(1) {
+						Some(&"long") => FlagType::Long,
+						Some(&"num") => FlagType::Num,
+						_ => FlagType::Char
+					};
+				},
+				Some(&"AF") if fields.len() >= 2 && fields[1].parse::<u32>().is_err() => {
+					aliases.push(parse_flags(fields[1], flag_type));
+				},
+				Some(&kind) if kind == "PFX" || kind == "SFX" => {
+					let is_suffix = kind == "SFX";
+					if fields.len() == 4 && fields[3].parse::<u32>().is_ok() && (fields[2] == "Y" || fields[2] == "N") {
+						// Header: SFX flag cross_product count
+						cross.insert((is_suffix, fields[1].to_string()), fields[2] == "Y");
+						continue;
+					}
+					if fields.len() < 4 {
+						return Err(error("bad affix rule"));
+					}
+					let flag = *parse_flags(fields[1], flag_type).first()
+						.ok_or_else(|| error("bad affix flag"))?;
+					let cross_product = *cross.get(&(is_suffix, fields[1].to_string()))
+						.ok_or_else(|| error("affix rule without header"))?;
+					let none_if_zero = |field: &str| if field == "0" { String::new() } else { field.to_string() };
+					// Continuation flags are not supported
+					let add = fields[3].split('/').next().unwrap_or("");
+					let affix = Affix {
+						flag,
+						cross_product,
+						strip: none_if_zero(fields[2]),
+						add: none_if_zero(add),
+						condition: parse_condition(fields.get(4).unwrap_or(&"."))
+					};
+					if is_suffix {
+						suffixes.push(affix);
+					}
+					else {
+						prefixes.push(affix);
+					}
+				},
+				_ => ()
+			}
+		}
+
+		let mut words: HashMap<String, Vec<u32>> = HashMap::new();
+		for (line_index, line) in dic.lines().enumerate() {
+			let line = line.trim();
+			// The first line is the word count
+			if line_index == 0 && line.parse::<u32>().is_ok() {
+				continue;
+			}
+			// Morphological fields follow a tab or a space
+			let entry = match line.split(['\t', ' ']).next() {
+				Some(entry) if !entry.is_empty() => entry,
+				_ => continue
+			};
+			let (word, flags) = match entry.find('/') {
+				Some(slash_index) => {
+					let flags_str = &entry[slash_index + 1..];
+					let flags = if aliases.is_empty() {
+						parse_flags(flags_str, flag_type)
+					}
+					else {
+						let alias_index: usize = flags_str.parse()
+							.map_err(|_| SpellError::BadFlagAlias { line_num: line_index + 1 })?;
+						aliases.get(alias_index.wrapping_sub(1)).cloned()
+							.ok_or(SpellError::BadFlagAlias { line_num: line_index + 1 })?
+					};
+					(&entry[..slash_index], flags)
+				},
+				None => (entry, Vec::new())
+			};
+			words.entry(word.to_string()).or_default().extend(flags);
+		}
+
+		Ok(Dictionary {
+			words,
+			prefixes,
+			suffixes,
+			extra_words: HashSet::new()
+		})
+	}
+
+	///////////////////////////////////////////////////////////////////////////
+	// Loads a .dic file and the .aff file next to it
+	pub fn load(dic_path: &str) -> Result<Dictionary, SpellError> {
+		let aff_path = match dic_path.strip_suffix(".dic") {
+			Some(base) => format!("{}.aff", base),
+			None => format!("{}.aff", dic_path)
+		};
+		let dic = charset::read_text_file(dic_path)
+			.map_err(|error| SpellError::Read { path: dic_path.to_string(), error })?;
+		let aff = charset::read_text_file(&aff_path)
+			.map_err(|error| SpellError::Read { path: aff_path.clone(), error })?;
+		Dictionary::parse(&aff, &dic)
+	}
+
+	// Adds the words of a word list, one word per line, # for comments
+	pub fn add_words(&mut self, content: &str) {
+		for line in content.lines() {
+			let word = line.trim();
+			if !word.is_empty() && !word.starts_with('#') {
+				self.extra_words.insert(word.to_string());
+			}
+		}
+	}
+
+	fn has_flag(&self, word: &str, flag: u32) -> bool {
+		self.words.get(word).is_some_and(|flags| flags.contains(&flag))
+	}
+
+	// Checks a word as it is written
+	fn check_exact(&self, word: &str) -> bool {
+		if self.words.contains_key(word) || self.extra_words.contains(word) {
+			return true;
+		}
+		for suffix in self.suffixes.iter() {
+			if let Some(base) = suffix.remove_suffix(word) {
+				if self.has_flag(&base, suffix.flag) {
+					return true;
+				}
+				if suffix.cross_product {
+					for prefix in self.prefixes.iter().filter(|prefix| prefix.cross_product) {
+						if let Some(root) = prefix.remove_prefix(&base) {
+							if self.has_flag(&root, suffix.flag) && self.has_flag(&root, prefix.flag) {
+								return true;
+							}
+						}
+					}
+				}
+			}
+		}
+		self.prefixes.iter().any(|prefix| {
+			prefix.remove_prefix(word).is_some_and(|base| self.has_flag(&base, prefix.flag))
+		})
+	}
+
+	///////////////////////////////////////////////////////////////////////////
+	// Checks a word. A capitalized word may be a lower case word at the start
+	// of a sentence, a word in capitals may be any word.
+	pub fn check(&self, word: &str) -> bool {
+		if self.check_exact(word) {
+			return true;
+		}
+		let lower = word.to_lowercase();
+		let mut chars = word.chars();
+		let first_upper = chars.next().is_some_and(|c| c.is_uppercase());
+		if !first_upper {
+			return false;
+		}
+		let rest: String = chars.collect();
+		if rest == rest.to_lowercase() {
+			return self.check_exact(&lower);
+		}
+		if word == word.to_uppercase() {
+			let mut capitalized: String = word.chars().take(1).collect();
+			capitalized.push_str(&lower.chars().skip(1).collect::<String>());
+			return self.check_exact(&lower) || self.check_exact(&capitalized);
+		}
+		false
+	}
+
+	///////////////////////////////////////////////////////////////////////////
+	// The only known word that differs by one or two accents, if there is
+	// exactly one
+	pub fn accent_suggestion(&self, word: &str) -> Option<String> {
+		let chars: Vec<char> = word.chars().collect();
+		let positions: Vec<usize> = (0..chars.len()).filter(|&index| !accented(chars[index]).is_empty()).collect();
+
+		let mut found: Vec<String> = Vec::new();
+		let mut try_word = |candidate: &[char]| {
+			let candidate: String = candidate.iter().collect();
+			if self.check(&candidate) && !found.contains(&candidate) {
+				found.push(candidate);
+			}
+		};
+		for (pos_index, &pos1) in positions.iter().enumerate() {
+			for &c1 in accented(chars[pos1]) {
+				let mut candidate = chars.clone();
+				candidate[pos1] = c1;
+				try_word(&candidate);
+				for &pos2 in positions[pos_index + 1..].iter() {
+					for &c2 in accented(chars[pos2]) {
+						let mut candidate2 = candidate.clone();
+						candidate2[pos2] = c2;
+						try_word(&candidate2);
+					}
+				}
+			}
+		}
+		if found.len() == 1 { found.pop() } else { None }
+	}
+}
+
+// Accented forms of a letter
+fn accented(c: char) -> &'static [char] {
+	match c {
+		'a' => &['à', 'â', 'á', 'ã', 'ä'],
+		'c' => &['ç'],
+		'e' => &['é', 'è', 'ê', 'ë'],
+		'i' => &['î', 'ï', 'í', 'ì'],
+		'n' => &['ñ'],
+		'o' => &['ô', 'ó', 'õ', 'ö', 'ò'],
+		'u' => &['ù', 'û', 'ü', 'ú'],
+		'A' => &['À', 'Â', 'Á', 'Ã', 'Ä'],
+		'C' => &['Ç'],
+		'E' => &['É', 'È', 'Ê', 'Ë'],
+		'I' => &['Î', 'Ï', 'Í', 'Ì'],
+		'N' => &['Ñ'],
+		'O' => &['Ô', 'Ó', 'Õ', 'Ö', 'Ò'],
+		'U' => &['Ù', 'Û', 'Ü', 'Ú'],
+		_ => &[]
+	}
+}
+
+#[test]
+fn test_dictionary() {
+	let aff = "SET UTF-8\n\
+SFX S Y 1\n\
+SFX S 0 s [^s]\n\
+SFX X Y 1\n\
+SFX X al aux al\n\
+PFX R Y 1\n\
+PFX R 0 re .\n";
+	let dic = "4\nécole/S\ncheval/X\nfaire/R\nParis\n";
+	let mut dict = Dictionary::parse(aff, dic).unwrap();
+	assert!(dict.check("école"));
+	assert!(dict.check("écoles"));
+	assert!(dict.check("École"));
+	assert!(dict.check("ÉCOLES"));
+	assert!(dict.check("chevaux"));
+	assert!(!dict.check("chevals"));
+	assert!(dict.check("refaire"));
+	assert!(dict.check("Paris"));
+	assert!(!dict.check("paris"));
+	assert!(!dict.check("Dupont"));
+	dict.add_words("# Names\nDupont\n");
+	assert!(dict.check("Dupont"));
+
+	assert_eq!(dict.accent_suggestion("Ecole"), Some("École".to_string()));
+	assert_eq!(dict.accent_suggestion("ecoles"), Some("écoles".to_string()));
+	assert_eq!(dict.accent_suggestion("truc"), None);
+}
+
+// An unknown word
+#[derive(Debug)]
+pub struct Misspelling {
+	pub num: u32,
+	// 0 when unknown
+	pub line_num: u32,
+	pub word: String,
+	pub suggestion: Option<String>
+}
+
+impl Misspelling {
+	// What is wrong, without the subtitle number
+	pub fn describe(&self) -> String {
+		match self.suggestion {
+			Some(ref suggestion) => format!("unknown word '{}', '{}'?", self.word, suggestion),
+			None => format!("unknown word '{}'", self.word)
+		}
+	}
+}
+
+impl fmt::Display for Misspelling {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "#{} {}", self.num, self.describe())
+	}
+}
+
+fn is_joiner(c: char) -> bool {
+	c == '\'' || c == '’' || c == '-'
+}
+
+// Words of a line of text. Apostrophes and hyphens between letters are part
+// of the word. Words with digits are not words.
+fn split_words(text: &str) -> Vec<&str> {
+	let chars: Vec<(usize, char)> = text.char_indices().collect();
+	let in_word = |index: usize| -> bool {
+		let c = chars[index].1;
+		c.is_alphanumeric() || (is_joiner(c) && index > 0 && index + 1 < chars.len()
+			&& chars[index - 1].1.is_alphanumeric() && chars[index + 1].1.is_alphanumeric())
+	};
+	let mut words: Vec<&str> = Vec::new();
+	let mut start: Option<usize> = None;
+	for index in 0..=chars.len() {
+		let word_char = index < chars.len() && in_word(index);
+		match (start, word_char) {
+			(None, true) => start = Some(chars[index].0),
+			(Some(word_start), false) => {
+				let end = chars.get(index).map_or(text.len(), |&(byte, _)| byte);
+				words.push(&text[word_start..end]);
+				start = None;
+			},
+			_ => ()
+		}
+	}
+	words.into_iter()
+		.filter(|word| word.chars().count() > 1 && !word.chars().any(|c| c.is_numeric()))
+		.collect()
+}
+
+#[test]
+fn test_split_words() {
+	assert_eq!(split_words("Aujourd'hui, qu'il vienne jusqu’à 3h."),
+		vec!["Aujourd'hui", "qu'il", "vienne", "jusqu’à"]);
+	assert_eq!(split_words("'You've' - isn't it? Peut-être -"), vec!["You've", "isn't", "it", "Peut-être"]);
+}
+
+// Checks a word, with ' or ’ as apostrophe
+fn check_apostrophes(dictionary: &Dictionary, word: &str) -> bool {
+	dictionary.check(word)
+	 || (word.contains('’') && dictionary.check(&word.replace('’', "'")))
+	 || (word.contains('\'') && dictionary.check(&word.replace('\'', "’")))
+}
+
+// The part of a word that the dictionary does not know, if any. Dictionaries
+// know elided forms like "qu'" or "l'" alone, and compound words may be
+// missing while their parts are known.
+fn unknown_part<'a>(dictionary: &Dictionary, word: &'a str) -> Option<&'a str> {
+	if check_apostrophes(dictionary, word) {
+		return None;
+	}
+	match word.char_indices().find(|&(_, c)| is_joiner(c)) {
+		Some((_, '-')) => word.split('-')
+			.filter(|part| part.chars().count() > 1)
+			.find_map(|part| unknown_part(dictionary, part)),
+		Some((index, c)) => {
+			let elided = &word[..index + c.len_utf8()];
+			if check_apostrophes(dictionary, elided) {
+				unknown_part(dictionary, &word[index + c.len_utf8()..])
+			}
+			else {
+				Some(word)
+			}
+		},
+		None => Some(word)
+	}
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Lists the unknown words of a file, as it will be once the rules are applied
+pub fn spell_check(sub_file: &SubtitleFile, dictionary: &Dictionary, rule_set: &RuleSet) -> Vec<Misspelling> {
+	let mut misspellings: Vec<Misspelling> = Vec::new();
+
+	// All the text of a subtitle is on one line in some formats
+	let one_line = match sub_file.format {
+		Format::Ass | Format::MicroDvd => true,
+		Format::Srt | Format::Vtt => false
+	};
+
+	for subtitle in sub_file.subtitles.iter() {
+		for text_index in 0..subtitle.texts.len() {
+			let text = reading::strip_tags(&txt_rep::replace_one(&subtitle.texts[text_index], rule_set));
+			for word in split_words(&text) {
+				let word = match unknown_part(dictionary, word) {
+					Some(word) => word,
+					None => continue
+				};
+				let line_num = if subtitle.line_num == 0 || one_line {
+					subtitle.line_num
+				}
+				else {
+					subtitle.line_num + text_index as u32
+				};
+				misspellings.push(Misspelling {
+					num: subtitle.num,
+					line_num,
+					word: word.to_string(),
+					suggestion: dictionary.accent_suggestion(word)
+				});
+			}
+		}
+	}
+	misspellings
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Adds the accent fixes of the unknown words to the rules, and returns them
+pub fn add_accent_fixes(rule_set: &mut RuleSet, sub_file: &SubtitleFile, dictionary: &Dictionary) -> Vec<(String, String)> {
+	let mut fixes: Vec<(String, String)> = Vec::new();
+	for misspelling in spell_check(sub_file, dictionary, rule_set) {
+		if let Some(suggestion) = misspelling.suggestion {
+			let fix = (misspelling.word, suggestion);
+			if !fixes.contains(&fix) {
+				fixes.push(fix);
+			}
+		}
+	}
+	for (word, suggestion) in fixes.iter() {
+		let what = format!(r"\b{}\b", regex::escape(word));
+		// Cannot fail, the word is escaped
+		rule_set.add_regex(what, suggestion.clone()).unwrap();
+	}
+	fixes
+}
+
+#[test]
+fn test_spell_check() {
+	let aff = "SFX S Y 1\nSFX S 0 s .\n";
+	let dic = "8\nécole/S\nva\nà\nl'\nqu'\nil\naujourd'hui\nvoit\n";
+	let dictionary = Dictionary::parse(aff, dic).unwrap();
+	let srt = "1\n00:00:01,000 --> 00:00:02,000\n<i>Il va</i> a l'Ecole\n\n\
+2\n00:00:03,000 --> 00:00:04,000\nEcoles, 3x\n\n\
+3\n00:00:05,000 --> 00:00:06,000\nAujourd’hui, qu'il voit l'école-à-l'école, jusqu'au\n";
+	let sub_file = ::srt::parse_subtitles(srt, Format::Srt, None).unwrap();
+	let mut rule_set = RuleSet { rules: Vec::new(), inverted_marks: false };
+	let misspellings = spell_check(&sub_file, &dictionary, &rule_set);
+	let words: Vec<&str> = misspellings.iter().map(|misspelling| &misspelling.word[..]).collect();
+	assert_eq!(words, vec!["Ecole", "Ecoles", "jusqu'au"]);
+	assert_eq!(misspellings[0].to_string(), "#1 unknown word 'Ecole', 'École'?");
+	assert_eq!(misspellings[1].line_num, 7);
+
+	let fixes = add_accent_fixes(&mut rule_set, &sub_file, &dictionary);
+	assert_eq!(fixes.len(), 2);
+	assert_eq!(txt_rep::replace_one("l'Ecole, Ecoles", &rule_set), "l'École, Écoles");
+	assert_eq!(spell_check(&sub_file, &dictionary, &rule_set).len(), 1);
+	assert_eq!(unknown_part(&dictionary, "qu'Ecole"), Some("Ecole"));
+	assert_eq!(unknown_part(&dictionary, "va-t-il"), None);
+}
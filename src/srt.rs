@@ -1,54 +1,226 @@
 use std;
-use std::io;
-use std::io::prelude::*;
 use std::str::FromStr;
-use std::fs::File;
-use std::io::{Error, ErrorKind};
+use std::io::Error;
 use std::str;
 
 use workfile;
-
-#[derive(Default)]
+use charset;
+use charset::{Encoding, TextFormat};
+use vtt;
+use ass;
+use microdvd;
+use fps::Fps;
+use error::{Diagnostic, LoadError, ParseError, SaveError};
+
+#[derive(Default, Clone)]
 pub struct Subtitle {
-	num: u32,
+	// Number as found in the file, even if out of sequence
+	pub num: u32,
 	pub time_from: i32,
 	pub time_to : i32,
-	pub texts: [String; 5],
-	pub text_count: u32
+	pub texts: Vec<String>,
+	// Empty lines after the one that ends the subtitle, written back as is
+	pub extra_blank_lines: u32,
+	// WebVTT cue identifier and cue settings (position, line, align...),
+	// or ASS event fields
+	pub ident: String,
+	pub settings: String,
+	// Blocks that are not cues (WebVTT NOTE, STYLE..., ASS Comment, broken
+	// SRT subtitles) found before this subtitle, kept verbatim so they can be
+	// written back
+	pub notes: Vec<String>,
+	// Line of the file where the text starts, 0 if unknown
+	pub line_num: u32
+}
+
+// Subtitle file formats we can read and write
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+	Srt,
+	Vtt,
+	Ass,
+	MicroDvd
+}
+
+impl Format {
+	pub fn from_name(name: &str) -> Option<Format> {
+		match name.to_lowercase().as_str() {
+			"srt" => Some(Format::Srt),
+			"vtt" | "webvtt" => Some(Format::Vtt),
+			"ass" | "ssa" => Some(Format::Ass),
+			"sub" | "microdvd" => Some(Format::MicroDvd),
+			_ => None
+		}
+	}
+
+	// Guesses the format from the file extension
+	pub fn from_path(file_path: &str) -> Option<Format> {
+		let ext = match std::path::Path::new(file_path).extension() {
+			Some(ext) => ext.to_string_lossy(),
+			None => return None
+		};
+		Format::from_name(&ext)
+	}
+}
+
+// A whole subtitle file: the subtitles plus what is needed to write it
+// back in its own format
+pub struct SubtitleFile {
+	pub format: Format,
+	// Header lines, verbatim (WebVTT: the WEBVTT line and what follows,
+	// ASS: the sections before the events)
+	pub header: Vec<String>,
+	pub subtitles: Vec<Subtitle>,
+	// Non-cue blocks found after the last subtitle
+	pub notes: Vec<String>,
+	// Frame rate of frame based formats (MicroDVD)
+	pub fps: Option<Fps>,
+	// Encoding, BOM and line endings to write the file with
+	pub text_format: TextFormat,
+	// Why the encoding was chosen when the file was loaded
+	pub encoding_reason: &'static str,
+	// Errors skipped by a lenient parsing
+	pub diagnostics: Vec<Diagnostic>
+}
+
+impl SubtitleFile {
+	pub fn new(format: Format) -> SubtitleFile {
+		SubtitleFile {
+			format,
+			header: Vec::new(),
+			subtitles: Vec::new(),
+			notes: Vec::new(),
+			fps: None,
+			text_format: TextFormat::default(),
+			encoding_reason: "",
+			diagnostics: Vec::new()
+		}
+	}
+
+	// Changes the format, forgetting what belongs to the previous format
+	pub fn convert(&mut self, format: Format) {
+		if format == self.format {
+			return;
+		}
+		self.format = format;
+		self.header.clear();
+		self.notes.clear();
+		for subtitle in self.subtitles.iter_mut() {
+			subtitle.ident.clear();
+			subtitle.settings.clear();
+			subtitle.notes.clear();
+		}
+
+		// Tell the frame rate to the players
+		if format == Format::MicroDvd {
+			if let Some(fps) = self.fps {
+				self.header.push(format!("{{1}}{{1}}{}", fps));
+			}
+		}
+	}
 }
 
 impl Subtitle {
 	pub fn to_string(&self) -> String {
-		let mut ret = self.num.to_string();
+		let mut ret = String::new();
+		for note in self.notes.iter() {
+			ret.push_str(note);
+			ret.push_str("\r\n");
+		}
+		ret.push_str(&self.num.to_string());
 		ret.push_str("\r\n");
 		ret.push_str(&format_srt_time_from_milli(self.time_from));
 		ret.push_str(" --> ");
 		ret.push_str(&format_srt_time_from_milli(self.time_to));
 		ret.push_str("\r\n");
-		for text_index in 0..self.text_count as usize {
-			ret.push_str(&self.texts[text_index]);
+		for text in self.texts.iter() {
+			ret.push_str(text);
 			ret.push_str("\r\n");
 		}
 		ret.push_str("\r\n");
+		for _ in 0..self.extra_blank_lines {
+			ret.push_str("\r\n");
+		}
 		ret
 	}
 
-	// Returns true if full
-	fn push_text(&mut self, line: &str) -> bool {
-		let next_index = self.text_count as usize;
-		if next_index == self.texts.len() {
-			true
+	pub fn push_text(&mut self, line: &str) {
+		self.texts.push(line.to_string());
+	}
+}
+
+// How a file with errors is parsed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseMode {
+	// The first error fails the parsing
+	Strict,
+	// Errors are recorded as diagnostics, the broken subtitles are kept as is
+	Lenient
+}
+
+// Parses the timing line of a SRT subtitle. The error comes with the column
+// of the bad part.
+fn parse_srt_timing(line: &str, line_num: usize) -> Result<(i32, i32), (ParseError, usize)> {
+	let arrow_index = match line.find("-->") {
+		Some(index) => index,
+		None => {
+			return Err((ParseError::BadDurationSeparator { line_num, text: line.to_string() }, 1));
 		}
-		else {
-			self.texts[next_index] = line.to_string();
-			self.text_count += 1;
-			false
+	};
+	let left = &line[0..arrow_index].trim();
+	let right = &line[arrow_index + 3..].trim();
+	// Columns count characters, from 1
+	let column_of = |part: &str| {
+		let start = part.as_ptr() as usize - line.as_ptr() as usize;
+		line[..start].chars().count() + 1
+	};
+
+	let time_from = match parse_srt_time(left) {
+		Some(milli) => milli,
+		None => {
+			return Err((ParseError::BadTimeFrom { line_num, text: left.to_string() }, column_of(left)));
+		}
+	};
+	let time_to = match parse_srt_time(right) {
+		Some(milli) => milli,
+		None => {
+			return Err((ParseError::BadTimeTo { line_num, text: right.to_string() }, column_of(right)));
 		}
+	};
+	Ok((time_from, time_to))
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Parses a SRT file, failing on the first error
+pub fn parse_srt(content: &str) -> Result<Vec<Subtitle>,ParseError> {
+	parse_srt_mode(content, ParseMode::Strict).map(|file| file.subtitles)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Parses a SRT file, keeping the broken subtitles as notes. What was wrong is
+// in the diagnostics of the file.
+pub fn parse_srt_lenient(content: &str) -> SubtitleFile {
+	match parse_srt_mode(content, ParseMode::Lenient) {
+		Ok(file) => file,
+		Err(_) => unreachable!()
 	}
 }
 
-pub fn parse_srt(content: &str) -> Result<Vec<Subtitle>,String> {
+///////////////////////////////////////////////////////////////////////////////
+// In lenient mode, a subtitle whose number or timing is bad is skipped
+// until the next line with a number or a timing. The skipped lines are kept
+// verbatim as notes of the next subtitle, or of the file, so they are
+// written back unchanged. A timing line without number gets the number
+// following the previous subtitle.
+pub fn parse_srt_mode(content: &str, mode: ParseMode) -> Result<SubtitleFile,ParseError> {
 	let mut subtitles: Vec<Subtitle> = Vec::new();
+	let mut diagnostics: Vec<Diagnostic> = Vec::new();
+	// Lines of the broken subtitles, as found
+	let mut skipped: Vec<String> = Vec::new();
+	// Number line of the current subtitle, as found
+	let mut num_line = "";
+	// The previous line was skipped as a bad number
+	let mut after_bad_num = false;
 
 	#[derive(Debug)]
 	enum State {
@@ -56,13 +228,25 @@ pub fn parse_srt(content: &str) -> Result<Vec<Subtitle>,String> {
 	    WantsDuration,
 	    WantsFirstText,
 	    WantsFirstTextAgain,
-	    WantsFollowingText
+	    WantsFollowingText,
+	    SkipsSubtitle
 	}
 	let mut state: State = State::WantsNum;
 
 	let mut subtitle: Subtitle = Default::default();
 	let mut line_num = 1;
 
+	// Fails in strict mode, records the error in lenient mode
+	let mut on_error = |error: ParseError, column: usize| -> Result<(), ParseError> {
+		match mode {
+			ParseMode::Strict => Err(error),
+			ParseMode::Lenient => {
+				diagnostics.push(Diagnostic { column, error });
+				Ok(())
+			}
+		}
+	};
+
 	for line_ori in content.lines() {
 		//println!("[{:?}] {}", state, line);
 
@@ -71,49 +255,71 @@ pub fn parse_srt(content: &str) -> Result<Vec<Subtitle>,String> {
 		let line = line_ori.trim_right();
 
 		match state {
-			State::WantsNum => {
+			State::WantsNum | State::SkipsSubtitle => {
 				if line.is_empty() {
 					// That's suspicious but accepted
 					// Stay in the WantsNum state
+					after_bad_num = false;
+					if let (State::WantsNum, Some(previous)) = (&state, subtitles.last_mut()) {
+						previous.extra_blank_lines += 1;
+					}
 				}
-				else {
-					subtitle.num = match u32::from_str(&line) {
-						Ok(val) => val,
-						Err(err) => {
-							return Err(format!("Bad number at line {}: {}: '{}'",
-								line_num, err, line));
-						}
-					};
+				else if let Ok(val) = u32::from_str(line) {
+					subtitle = Default::default();
+					subtitle.num = val;
+					subtitle.notes = std::mem::take(&mut skipped);
+					num_line = line_ori;
 					state = State::WantsDuration;
 				}
-			},
-			State::WantsDuration => {
-				let arrow_index = match line.find("-->") {
-					Some(index) => index,
-					None => {
-						return Err(format!("Bad duration separator at line {}: {}",
-							line_num, line));
+				else {
+					let bad_num = after_bad_num;
+					after_bad_num = false;
+					if let (State::WantsNum, Err(error)) = (&state, u32::from_str(line)) {
+						on_error(ParseError::BadNumber { line_num: line_num as usize,
+							text: line.to_string(), error }, 1)?;
+						after_bad_num = true;
 					}
-				};
-				let left = &line[0..arrow_index].trim();
-				let right = &line[arrow_index + 3..].trim();
-
-				subtitle.time_from = match parse_srt_time(left) {
-					Some(milli) => milli,
-					None => {
-						return Err(format!("Bad time 'from' at line {}: {}",
-							line_num, left));
+					// Maybe the number is missing or was bad
+					match parse_srt_timing(line, line_num as usize) {
+						Ok((time_from, time_to)) => {
+							if bad_num {
+								// Replaced by the number given
+								skipped.pop();
+							}
+							subtitle = Default::default();
+							subtitle.num = subtitles.last().map_or(1, |previous| previous.num + 1);
+							subtitle.time_from = time_from;
+							subtitle.time_to = time_to;
+							subtitle.notes = std::mem::take(&mut skipped);
+							state = State::WantsFirstText;
+						},
+						Err(_) => state = State::SkipsSubtitle
 					}
-				};
-				subtitle.time_to = match parse_srt_time(right) {
-					Some(milli) => milli,
-					None => {
-						return Err(format!("Bad time 'to' at line {}: {}",
-							line_num, right));
+				}
+				if let State::SkipsSubtitle = state {
+					skipped.push(line_ori.to_string());
+				}
+				else {
+					after_bad_num = false;
+				}
+			},
+			State::WantsDuration => {
+				match parse_srt_timing(line, line_num as usize) {
+					Ok((time_from, time_to)) => {
+						subtitle.time_from = time_from;
+						subtitle.time_to = time_to;
+						state = State::WantsFirstText;
+					},
+					Err((error, column)) => {
+						on_error(error, column)?;
+						let notes = std::mem::take(&mut subtitle.notes);
+						subtitle = Default::default();
+						skipped = notes;
+						skipped.push(num_line.to_string());
+						skipped.push(line_ori.to_string());
+						state = State::SkipsSubtitle;
 					}
-				};
-
-				state = State::WantsFirstText;
+				}
 			},
 			State::WantsFirstText => {
 				if line.is_empty() {
@@ -122,6 +328,7 @@ pub fn parse_srt(content: &str) -> Result<Vec<Subtitle>,String> {
 				}
 				else {
 					subtitle.push_text(line);
+					subtitle.line_num = line_num;
 					state = State::WantsFollowingText;
 				}
 			},
@@ -138,6 +345,7 @@ pub fn parse_srt(content: &str) -> Result<Vec<Subtitle>,String> {
 							subtitles.push(subtitle);
 							subtitle = Default::default();
 							subtitle.num = val;
+							num_line = line_ori;
 							state = State::WantsDuration;
 							true
 						}
@@ -152,6 +360,7 @@ pub fn parse_srt(content: &str) -> Result<Vec<Subtitle>,String> {
 					if !new_one {
 						// The non-empty line is the line we were waiting for
 						subtitle.push_text(line);
+						subtitle.line_num = line_num;
 						state = State::WantsFollowingText;
 					}
 				}
@@ -163,10 +372,7 @@ pub fn parse_srt(content: &str) -> Result<Vec<Subtitle>,String> {
 					state = State::WantsNum;
 				}
 				else {
-					if subtitle.push_text(line) {
-						return Err(format!("Too much text at line {} sub {}",
-							line_num, subtitle.num));
-					}
+					subtitle.push_text(line);
 				}
 			}
 		}
@@ -175,10 +381,20 @@ pub fn parse_srt(content: &str) -> Result<Vec<Subtitle>,String> {
 
 	// Push the last subtitle because we may not have an empty line
 	// to know the last subtitle ended
-	if subtitle.text_count > 0 {
+	if !subtitle.texts.is_empty() {
 		subtitles.push(subtitle);
 	}
-	Ok(subtitles)
+	else if let State::WantsDuration = state {
+		// A number line ends the file
+		on_error(ParseError::MissingCueTiming { line_num: line_num as usize }, 1)?;
+		skipped = subtitle.notes;
+		skipped.push(num_line.to_string());
+	}
+	let mut file = SubtitleFile::new(Format::Srt);
+	file.subtitles = subtitles;
+	file.notes = skipped;
+	file.diagnostics = diagnostics;
+	Ok(file)
 }
 
 #[test]
@@ -199,10 +415,12 @@ mango"#;
 		let subs = subs_res.unwrap();
 		assert_eq!(subs.len(), 2);
 		assert_eq!(subs[0].num, 42);
-		assert!(subs[0].text_count == 1);
+		assert!(subs[0].texts.len() == 1);
 		assert!(subs[0].texts[0] == "suspicious empty line above");
+		assert_eq!(subs[0].line_num, 4);
+		assert_eq!(subs[1].line_num, 8);
 		assert!(subs[1].num == 43);
-		assert!(subs[1].text_count == 1);
+		assert!(subs[1].texts.len() == 1);
 		assert!(subs[1].texts[0] == "mango");
 	}
 
@@ -234,7 +452,7 @@ hi"#;
 		let subs = subs_res.unwrap();
 		assert!(subs.len() == 2);
 		assert!(subs[0].num == 42);
-		assert!(subs[0].text_count == 2);
+		assert!(subs[0].texts.len() == 2);
 		assert!(subs[0].texts[0] == "hello");
 		assert!(subs[0].texts[1] == "mister");
 	}
@@ -253,9 +471,9 @@ hi"#;
 		let subs = subs_res.unwrap();
 		assert!(subs.len() == 2);
 		assert!(subs[0].num == 42);
-		assert!(subs[0].text_count == 0);
+		assert!(subs[0].texts.is_empty());
 		assert!(subs[1].num == 43);
-		assert!(subs[1].text_count == 1);
+		assert!(subs[1].texts.len() == 1);
 		assert!(subs[1].texts[0] == "hi");
 	}
 
@@ -275,13 +493,25 @@ end"#;
 		let subs = subs_res.unwrap();
 		assert_eq!(subs.len(), 2);
 		assert_eq!(subs[0].num, 51);
-		assert_eq!(subs[0].text_count, 1);
+		assert_eq!(subs[0].texts.len(), 1);
 		assert_eq!(subs[0].texts[0], "begin");
+		assert_eq!(subs[0].extra_blank_lines, 1);
+		assert_eq!(subs[0].to_string(), "51\r\n00:00:16,087 --> 00:00:19,911\r\nbegin\r\n\r\n\r\n");
 		assert_eq!(subs[1].num, 52);
-		assert_eq!(subs[1].text_count, 1);
+		assert_eq!(subs[1].texts.len(), 1);
 		assert_eq!(subs[1].texts[0], "end");
 	}
 
+	///////////////////////////////////
+	// Many lines, like song lyrics
+	{
+		let srt = "7\n00:00:16,087 --> 00:00:19,911\n1\n2\n3\n4\n5\n6\n7\n";
+		let subs = parse_srt(srt).unwrap();
+		assert_eq!(subs.len(), 1);
+		assert_eq!(subs[0].texts.len(), 7);
+		assert_eq!(subs[0].texts[6], "7");
+	}
+
 	///////////////////////////////////
 	// Fake empty line (contains spaces)
 	{
@@ -297,145 +527,171 @@ end"#;
 		let subs = subs_res.unwrap();
 		assert_eq!(subs.len(), 2);
 		assert_eq!(subs[0].num, 61);
-		assert_eq!(subs[0].text_count, 1);
+		assert_eq!(subs[0].texts.len(), 1);
 		assert_eq!(subs[0].texts[0], "begin");
 		assert_eq!(subs[1].num, 62);
-		assert_eq!(subs[1].text_count, 1);
+		assert_eq!(subs[1].texts.len(), 1);
 		assert_eq!(subs[1].texts[0], "end");
 	}
 }
 
-///////////////////////////////////////////////////////////////////////////////
-const W1252_80_9F: [char; 32] = [
-	'\u{20ac}', '\u{0081}', '\u{201a}', '\u{0192}',
-	'\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
-	'\u{02c6}', '\u{2030}', '\u{0160}', '\u{2039}',
-	'\u{0152}', '\u{008d}', '\u{017d}', '\u{008f}',
-	'\u{0090}', '\u{2018}', '\u{2019}', '\u{201c}',
-	'\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
-	'\u{02dc}', '\u{2122}', '\u{0161}', '\u{203a}',
-	'\u{0153}', '\u{009d}', '\u{017e}', '\u{0178}'
-	];
-
-fn decode_windows_1252(content: &[u8]) -> String {
-	let mut ret = String::with_capacity(content.len() * 2);
+#[test]
+fn test_parse_srt_lenient() {
+	let srt = "1\n00:00:01,000 --> 00:00:02,000\nfirst\n\n\
+2\n00:00:03,000 -> 00:00:04,000\nbad separator\n\n\
+3\n00:00:05,000 --> 00:00:06,000\nthird\n\n\
+x4\n00:00:07,000 --> 00:00:08,000\nno number\n\n\
+5\n00:00:09,000 --> 00:00:1O,000\nbad time\n\n\
+6\n00:00:11,000 --> 00:00:12,000\nlast\n";
+
+	match parse_srt(srt) {
+		Ok(_) => panic!("strict parsing should fail"),
+		Err(error) => assert_eq!(error.line_num(), 6)
+	}
 
-	for cp8 in content {
-		let c = if 0x80 <= *cp8 && *cp8 <= 0x9f {
-			W1252_80_9F[(*cp8 as usize) - 0x80]
-		}
-		else {
-			*cp8 as char
-		};
-		ret.push(c);
+	let file = parse_srt_lenient(srt);
+	let subs = &file.subtitles;
+	let diagnostics = &file.diagnostics;
+	let texts: Vec<&str> = subs.iter().map(|sub| sub.texts[0].as_str()).collect();
+	assert_eq!(texts, vec!["first", "third", "no number", "last"]);
+	assert_eq!(subs[2].num, 4);
+	assert_eq!(subs[2].time_from, 7_000);
+
+	assert_eq!(diagnostics.len(), 3);
+	assert_eq!(diagnostics[0].error.line_num(), 6);
+	assert_eq!(diagnostics[1].error.line_num(), 13);
+	assert_eq!(diagnostics[1].column, 1);
+	assert_eq!(diagnostics[2].error.line_num(), 18);
+	assert_eq!(diagnostics[2].column, 18);
+	match diagnostics[2].error {
+		ParseError::BadTimeTo { .. } => (),
+		ref error => panic!("{}", error)
 	}
-	ret
-}
 
-#[test]
-fn test_decode_windows_1252() {
-    let raw = [
-    	0x64u8, 0xe9, 0x6a, 0xe0,
-    	0x20,
-    	0x62,0x9c,0x75,0x66,
-    	0x20,
-    	0x33,0x80];
-    let text = decode_windows_1252(&raw);
-    assert!(text == "déjà bœuf 3€");
+	// The broken subtitles are written back as they were
+	assert_eq!(subs[1].notes, vec!["2", "00:00:03,000 -> 00:00:04,000", "bad separator", ""]);
+	let expected = srt.replace("x4\n", "4\n").replace('\n', "\r\n") + "\r\n";
+	assert_eq!(format_subtitles(&file), expected);
+
+	let file = parse_srt_lenient("1\n00:00:01,000 --> 00:00:02,000\nfirst\n\n2\n00:00:03,000 -> 00:00:04,000\nlast\n");
+	assert_eq!(file.subtitles.len(), 1);
+	assert_eq!(file.notes, vec!["2", "00:00:03,000 -> 00:00:04,000", "last"]);
+
+	// A number without timing at the end of the file
+	let srt = "1\n00:00:01,000 --> 00:00:02,000\nfirst\n\n2\n";
+	assert_eq!(parse_srt(srt).err().map(|error| error.line_num()), Some(6));
+	let file = parse_srt_lenient(srt);
+	assert_eq!(file.subtitles.len(), 1);
+	assert_eq!(file.diagnostics.len(), 1);
+	assert_eq!(file.diagnostics[0].error.line_num(), 6);
+	assert_eq!(file.notes, vec!["2"]);
+	assert_eq!(format_subtitles(&file), srt.replace('\n', "\r\n"));
 }
 
-const BOM: [u8;3] = [0xEF, 0xBB, 0xBF];
-
 ///////////////////////////////////////////////////////////////////////////////
-pub fn load_subtitles(file_path: &str) -> Result<Vec<Subtitle>,String> {
-	let content = {
-		let mut file = match File::open(file_path) {
-			Ok(file) => file,
-			Err(err) => {
-				return Err(format!("Cannot open file: {}", err));
-			}
-		};
-		let mut bytes = Vec::new();
-		if let Err(err) = file.read_to_end(&mut bytes) {
-			return Err(format!("File read error: {}", err));
-		};
-		bytes
-	};
+// Parses the content of a subtitle file of the given format. The frame
+// rate is needed by frame based formats if the file does not tell it.
+pub fn parse_subtitles(content: &str, format: Format, fps: Option<Fps>) -> Result<SubtitleFile,ParseError> {
+	parse_subtitles_mode(content, format, fps, ParseMode::Strict)
+}
 
-	// Detect encoding
+///////////////////////////////////////////////////////////////////////////////
+// Same as parse_subtitles, SRT files can also be parsed leniently. The
+// errors skipped are in the diagnostics of the file.
+pub fn parse_subtitles_mode(content: &str, format: Format, fps: Option<Fps>, mode: ParseMode)
+	-> Result<SubtitleFile,ParseError> {
+	match format {
+		Format::Srt => {
+			parse_srt_mode(content, mode)
+		},
+		Format::Vtt => vtt::parse_vtt(content),
+		Format::Ass => ass::parse_ass(content),
+		Format::MicroDvd => microdvd::parse_microdvd(content, fps)
+	}
+}
 
-	let has_bom = {
-		if content.len() >= 3 && content[0..3] == BOM {
-			true
+///////////////////////////////////////////////////////////////////////////////
+// Loads a subtitle file. If no format is given, it is guessed from the
+// file extension, then from the content.
+pub fn load_subtitles(file_path: &str, format: Option<Format>, fps: Option<Fps>,
+	encoding: Option<Encoding>, mode: ParseMode) -> Result<SubtitleFile,LoadError> {
+	let decoded = charset::read_text_file_format(file_path, encoding)?;
+	let content_str = decoded.text;
+
+	let format = match format.or_else(|| Format::from_path(file_path)) {
+		Some(format) => format,
+		None => if content_str.starts_with("WEBVTT") {
+			Format::Vtt
+		}
+		else if content_str.starts_with("[Script Info]") {
+			Format::Ass
+		}
+		else if content_str.starts_with('{') {
+			Format::MicroDvd
 		}
 		else {
-			false
+			Format::Srt
 		}
 	};
+	let mut file = parse_subtitles_mode(&content_str, format, fps, mode)?;
+	file.text_format = decoded.text_format;
+	file.encoding_reason = decoded.reason;
+	Ok(file)
+}
 
-	// Temporary String in case the content should be recreated
-	// from windows-1252 to utf-8
-	let tmp_str;
-
-	let content_str = if has_bom {
-		match str::from_utf8(&content[3..]) {
-			Ok(res) => res,
-			Err(err) => {
-				return Err(format!("Invalid UTF-8: {}", err));
+///////////////////////////////////////////////////////////////////////////////
+// Formats subtitles to the text of a file, in the format of the file
+pub fn format_subtitles(file: &SubtitleFile) -> String {
+	match file.format {
+		Format::Srt => {
+			let mut ret = String::new();
+			for subtitle in file.subtitles.iter() {
+				ret.push_str(&subtitle.to_string());
 			}
-		}
-	}
-	else {
-		// Check if it is UTF-8 without BOM
-		match str::from_utf8(&content) {
-			Ok(res) => res,
-			Err(_) => {
-				// Assume it is windows-1252
-				tmp_str = decode_windows_1252(&content);
-				tmp_str.as_str()
+			for note in file.notes.iter() {
+				ret.push_str(note);
+				ret.push_str("\r\n");
 			}
-		}
-	};
-
-	return parse_srt(&content_str);
+			ret
+		},
+		Format::Vtt => vtt::format_vtt(file),
+		Format::Ass => ass::format_ass(file),
+		Format::MicroDvd => microdvd::format_microdvd(file)
+	}
 }
 
 ///////////////////////////////////////////////////////////////////////////////
-pub fn save_subtitles(subtitles: &Vec<Subtitle>, file_path: &str) -> io::Result<()> {
-
-	let mut work_file = match workfile::WorkFile::create(file_path) {
-		Ok(file) => file,
-		Err(err) => {
-			println!("Cannot create file {}", err);
-			return Err(err);
-		}
-	};
-	match work_file.write(&BOM) {
-		Ok(len) => if len != BOM.len() {
-			println!("Cannot write BOM: not enough space");
-			return Err(Error::new(ErrorKind::Other, "bad len BOM"));
+// Saves subtitles with their text format. Returns the number of characters
+// the encoding cannot represent, written as '?'.
+pub fn save_subtitles(file: &SubtitleFile, file_path: &str) -> Result<usize,SaveError> {
+	let mut work_file = workfile::WorkFile::create(file_path).map_err(SaveError::Create)?;
+	let data_str = format_subtitles(file);
+	let (data, unmappable) = charset::encode(&data_str, &file.text_format);
+	match work_file.write(&data) {
+		Ok(len) => if len != data.len() {
+			return Err(SaveError::Write(Error::other("not enough space")));
 		},
 		Err(err) => {
-			println!("Cannot write BOM: {}", err);
-			return Err(err);
+			return Err(SaveError::Write(err));
 		}
 	}
-	for subtitle in subtitles.iter() {
-		let data_str = subtitle.to_string();
-		let data = data_str.as_bytes();
-		match work_file.write(data) {
-			Ok(len) => if len != data.len() {
-				println!("Cannot write subtitle: not enough space");
-				return Err(Error::new(ErrorKind::Other, "bad len"));
-			},
-			Err(err) => {
-				println!("Cannot write subtitle: {}", err);
-				return Err(err);
-			}
-		}
+	work_file.commit().map_err(SaveError::Commit)?;
+	Ok(unmappable)
+}
+
+#[test]
+fn test_save_subtitles_commit_error() {
+	let dir = std::env::temp_dir().join(format!("fixsrt_save_{}", std::process::id()));
+	// A non empty directory cannot be replaced by the working file
+	std::fs::create_dir_all(dir.join("movie.srt").join("inside")).unwrap();
+	let file_path = dir.join("movie.srt").to_string_lossy().into_owned();
+	let file = parse_srt_lenient("1\n00:00:01,000 --> 00:00:02,000\nHello\n");
+	match save_subtitles(&file, &file_path) {
+		Err(SaveError::Commit(_)) => (),
+		_ => panic!("commit error expected")
 	}
-	work_file.commit();
-	Ok(())
+	assert!(!dir.join("movie.srt.work").exists());
+	std::fs::remove_dir_all(&dir).unwrap();
 }
 
 ///////////////////////////////////////////////////////////////////////////////
@@ -563,7 +819,7 @@ pub fn parse_srt_time(time_str: &str) -> Option<i32> {
     }
 }
 
-fn format_srt_time_from_milli(milli: i32) -> String {
+pub fn format_srt_time_from_milli(milli: i32) -> String {
 	let milli_abs = if milli < 0 { -milli } else { milli };
 	let sign_str = if milli < 0 { "-" } else { "" };
 
@@ -661,3 +917,105 @@ yop
 	let subs = subs_res.unwrap();
 	assert!(!should_keep_last_sub(&subs));
 }
+
+///////////////////////////////////////////////////////////////////////////////
+// Moves all subtitles by time_shift_ms, then moves them more and more until
+// the last one is moved by time_shift_ms + time_stretch_ms
+pub fn shift_and_stretch(subtitles: &mut Vec<Subtitle>,
+                         time_shift_ms: i32, time_stretch_ms: i32) {
+
+	if time_shift_ms == 0 && time_stretch_ms == 0 {
+		return;
+	}
+	let sub_count = subtitles.len();
+	if sub_count == 0 {
+		return;
+	}
+
+	if sub_count > i32::MAX as usize {
+		// Too many subtitles
+		return;
+	};
+
+	// Compute the limit for stretching
+	let sub_count_i32 = if should_keep_last_sub(subtitles) {
+		sub_count as i32
+	}
+	else {
+		(sub_count - 1) as i32
+	};
+
+	for (sub_index, subtitle) in subtitles.iter_mut().enumerate() {
+		let sub_index = sub_index as i32;
+
+		subtitle.time_from += time_shift_ms;
+		subtitle.time_to += time_shift_ms;
+
+		if time_stretch_ms != 0 && sub_count_i32 > 1 && sub_index < sub_count_i32 {
+			let stretching = (sub_index * time_stretch_ms) / (sub_count_i32 - 1);
+			subtitle.time_from += stretching;
+			subtitle.time_to += stretching;
+		}
+	}
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Repairs the text that was decoded with the wrong encoding. Returns the
+// numbers of the subtitles repaired. Nothing is repaired unless most of the
+// lines with non-ASCII characters look mis-decoded, as valid text may look
+// so by chance.
+pub fn repair_mojibake(subtitles: &mut [Subtitle]) -> Vec<u32> {
+	let mut non_ascii = 0;
+	let mut mis_decoded = 0;
+	for text in subtitles.iter().flat_map(|subtitle| subtitle.texts.iter()) {
+		if !text.is_ascii() {
+			non_ascii += 1;
+			if charset::undo_mojibake(text).is_some() {
+				mis_decoded += 1;
+			}
+		}
+	}
+	let mut repaired: Vec<u32> = Vec::new();
+	if mis_decoded * 2 <= non_ascii {
+		return repaired;
+	}
+	for subtitle in subtitles.iter_mut() {
+		let mut changed = false;
+		for text in subtitle.texts.iter_mut() {
+			if let Some(new_text) = charset::undo_mojibake(text) {
+				*text = new_text;
+				changed = true;
+			}
+		}
+		if changed {
+			repaired.push(subtitle.num);
+		}
+	}
+	repaired
+}
+
+#[test]
+fn test_repair_mojibake() {
+	let subtitle = |num: u32, text: &str| Subtitle {
+		num,
+		texts: vec![text.to_string()],
+		..Default::default()
+	};
+	let mut subtitles = vec![
+		subtitle(1, "Ã‡a va, l'Ã©tÃ©"),
+		subtitle(2, "Hello"),
+		subtitle(3, "C'est dÃ©jÃ  fini")
+	];
+	assert_eq!(repair_mojibake(&mut subtitles), vec![1, 3]);
+	assert_eq!(subtitles[2].texts[0], "C'est déjà fini");
+
+	// Valid Portuguese text is left as is
+	let mut subtitles = vec![
+		subtitle(1, "A IRMÃ DELE CHEGOU"),
+		subtitle(2, "A IRMÃ, O IRMÃO"),
+		subtitle(3, "Ã©")
+	];
+	assert!(repair_mojibake(&mut subtitles).is_empty());
+	assert_eq!(subtitles[0].texts[0], "A IRMÃ DELE CHEGOU");
+	assert_eq!(subtitles[2].texts[0], "Ã©");
+}
@@ -0,0 +1,397 @@
+use std;
+use srt;
+use srt::Subtitle;
+use error::SyncError;
+
+// A linear time transform: new_time = old_time * scale + offset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Linear {
+	pub scale: f64,
+	pub offset: f64
+}
+
+impl Linear {
+	// The transform going through the two points (from1, to1) and (from2, to2)
+	pub fn through(from1: i32, to1: i32, from2: i32, to2: i32) -> Option<Linear> {
+		if from1 == from2 {
+			return None;
+		}
+		let scale = (to2 - to1) as f64 / (from2 - from1) as f64;
+		let offset = to1 as f64 - scale * from1 as f64;
+		Some(Linear { scale, offset })
+	}
+
+	pub fn apply(&self, milli: i32) -> i32 {
+		(milli as f64 * self.scale + self.offset).round() as i32
+	}
+}
+
+// The time a subtitle should start at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Anchor {
+	pub num: u32,
+	pub time: i32
+}
+
+impl Anchor {
+	// Parses "NUM=TIME", for example "12=00:01:03,200"
+	pub fn parse(anchor_str: &str) -> Option<Anchor> {
+		let equal_index = anchor_str.find('=')?;
+		let num = anchor_str[..equal_index].trim().parse().ok()?;
+		let time = srt::parse_srt_time(anchor_str[equal_index + 1..].trim())?;
+		Some(Anchor { num, time })
+	}
+}
+
+#[test]
+fn test_anchor_parse() {
+	assert_eq!(Anchor::parse("12=00:01:03,200"), Some(Anchor { num: 12, time: 63_200 }));
+	assert_eq!(Anchor::parse("840 = 1:32:10.050"), Some(Anchor { num: 840, time: 5_530_050 }));
+	assert_eq!(Anchor::parse("12"), None);
+	assert_eq!(Anchor::parse("x=00:01:03,200"), None);
+	assert_eq!(Anchor::parse("12=abc"), None);
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Moves and stretches all subtitles so that the two anchored subtitles
+// start at the given times. Subtitles are found by their number, which is
+// their position for formats without numbers.
+pub fn sync_two_points(subtitles: &mut [Subtitle], anchor1: Anchor, anchor2: Anchor)
+	-> Result<Linear,SyncError> {
+
+	let find_start = |anchor: Anchor| {
+		match subtitles.iter().find(|subtitle| subtitle.num == anchor.num) {
+			Some(subtitle) => Ok(subtitle.time_from),
+			None => Err(SyncError::NoSubtitle(anchor.num))
+		}
+	};
+	let from1 = find_start(anchor1)?;
+	let from2 = find_start(anchor2)?;
+
+	let linear = match Linear::through(from1, anchor1.time, from2, anchor2.time) {
+		Some(linear) => linear,
+		None => {
+			return Err(SyncError::SameStart(anchor1.num, anchor2.num));
+		}
+	};
+
+	for subtitle in subtitles.iter_mut() {
+		subtitle.time_from = linear.apply(subtitle.time_from);
+		subtitle.time_to = linear.apply(subtitle.time_to);
+	}
+	Ok(linear)
+}
+
+#[test]
+fn test_sync_two_points() {
+	let srt = "12\n00:00:10,000 --> 00:00:12,000\na\n\n\
+13\n00:00:20,000 --> 00:00:22,000\nb\n\n\
+14\n00:00:30,000 --> 00:00:32,000\nc\n";
+	let mut subs = srt::parse_srt(srt).unwrap();
+	let res = sync_two_points(&mut subs,
+		Anchor::parse("12=11").unwrap(), Anchor::parse("14=00:00:33").unwrap());
+	assert!(res.is_ok(), "{}", res.err().unwrap());
+	assert_eq!(res.unwrap(), Linear { scale: 1.1, offset: 0.0 });
+	assert_eq!(subs[0].time_from, 11_000);
+	assert_eq!(subs[0].time_to, 13_200);
+	assert_eq!(subs[1].time_from, 22_000);
+	assert_eq!(subs[2].time_from, 33_000);
+
+	assert!(sync_two_points(&mut subs,
+		Anchor::parse("12=11").unwrap(), Anchor::parse("15=40").unwrap()).is_err());
+	assert!(sync_two_points(&mut subs,
+		Anchor::parse("12=11").unwrap(), Anchor::parse("12=40").unwrap()).is_err());
+}
+
+// Offsets tried when matching a reference, and their step
+const MAX_OFFSET: i64 = 10 * 60 * 1000;
+const OFFSET_STEP: i64 = 100;
+// Cost of changing the offset between two subtitles, in milliseconds of
+// overlap with the reference
+const SPLIT_PENALTY: i64 = 5000;
+// Offset changes above this are breaks, smaller ones are drift
+const BREAK_MIN: i64 = 1000;
+// Frame rate ratios tried before matching (25 / 23.976, 25 / 24...)
+const SCALES: &[(i64, i64)] = &[
+	(1, 1),
+	(25 * 1001, 24000), (24000, 25 * 1001),
+	(25, 24), (24, 25),
+	(1001, 1000), (1000, 1001)
+];
+
+// Time covered by the reference subtitles before a given time
+struct Coverage {
+	intervals: Vec<(i64, i64)>,
+	covered_before: Vec<i64>
+}
+
+impl Coverage {
+	fn new(reference: &[Subtitle]) -> Coverage {
+		let mut raw: Vec<(i64, i64)> = reference.iter()
+			.filter(|subtitle| subtitle.time_to > subtitle.time_from)
+			.map(|subtitle| (subtitle.time_from as i64, subtitle.time_to as i64))
+			.collect();
+		raw.sort();
+
+		// Merge overlapping subtitles
+		let mut intervals: Vec<(i64, i64)> = Vec::new();
+		for (from, to) in raw {
+			if let Some(last) = intervals.last_mut() {
+				if from <= last.1 {
+					if to > last.1 {
+						last.1 = to;
+					}
+					continue;
+				}
+			}
+			intervals.push((from, to));
+		}
+
+		let mut covered_before = Vec::with_capacity(intervals.len());
+		let mut covered = 0;
+		for &(from, to) in intervals.iter() {
+			covered_before.push(covered);
+			covered += to - from;
+		}
+		Coverage { intervals, covered_before }
+	}
+
+	// Times must not decrease between calls sharing the same index
+	fn at(&self, time: i64, index: &mut usize) -> i64 {
+		while *index + 1 < self.intervals.len() && self.intervals[*index + 1].0 <= time {
+			*index += 1;
+		}
+		match self.intervals.get(*index) {
+			Some(&(from, to)) if from <= time => {
+				let inside = if time < to { time - from } else { to - from };
+				self.covered_before[*index] + inside
+			},
+			_ => 0
+		}
+	}
+
+	// Index to start the calls of at() with
+	fn start_index(&self, time: i64) -> usize {
+		let next = self.intervals.partition_point(|&(from, _)| from <= time);
+		if next == 0 { 0 } else { next - 1 }
+	}
+}
+
+// Finds the best offset of each subtitle, given as (from, to) sorted by
+// start time: the sum of the overlaps with the reference, minus the split
+// penalties, is maximal. Returns the score and the offset of each subtitle.
+fn best_offsets(times: &[(i64, i64)], coverage: &Coverage, with_offsets: bool)
+	-> (i64, Vec<i64>) {
+
+	let offset_count = (2 * MAX_OFFSET / OFFSET_STEP + 1) as usize;
+	let offset_at = |index: usize| -MAX_OFFSET + index as i64 * OFFSET_STEP;
+
+	// Best index of a row, the smallest offset wins in case of equality
+	let best_of = |row: &[i64]| -> usize {
+		let mut best = offset_count / 2;
+		for index in 0..offset_count {
+			if row[index] > row[best]
+			 || (row[index] == row[best] && offset_at(index).abs() < offset_at(best).abs()) {
+				best = index;
+			}
+		}
+		best
+	};
+
+	let mut prev: Vec<i64> = vec![0; offset_count];
+	let mut cur: Vec<i64> = vec![0; offset_count];
+	// For each subtitle and offset: true if the previous subtitle had
+	// another offset, the best one of its row
+	let mut switched: Vec<Vec<bool>> = Vec::new();
+	let mut bests: Vec<usize> = Vec::new();
+
+	for (sub_index, &(from, to)) in times.iter().enumerate() {
+		let prev_best = if sub_index == 0 { 0 } else { best_of(&prev) };
+		let switch_score = prev[prev_best] - SPLIT_PENALTY;
+		let mut switched_row = if with_offsets { vec![false; offset_count] } else { Vec::new() };
+
+		let mut index_from = coverage.start_index(from - MAX_OFFSET);
+		let mut index_to = coverage.start_index(to - MAX_OFFSET);
+		for offset_index in 0..offset_count {
+			let offset = offset_at(offset_index);
+			let overlap = coverage.at(to + offset, &mut index_to)
+				- coverage.at(from + offset, &mut index_from);
+
+			let stay_score = prev[offset_index];
+			cur[offset_index] = overlap + if sub_index > 0 && switch_score > stay_score {
+				if with_offsets {
+					switched_row[offset_index] = true;
+				}
+				switch_score
+			}
+			else {
+				stay_score
+			};
+		}
+		std::mem::swap(&mut prev, &mut cur);
+		if with_offsets {
+			switched.push(switched_row);
+			bests.push(prev_best);
+		}
+	}
+
+	let last_best = best_of(&prev);
+	let score = prev[last_best];
+	if !with_offsets {
+		return (score, Vec::new());
+	}
+
+	// Walk back
+	let mut offsets = vec![0; times.len()];
+	let mut offset_index = last_best;
+	for sub_index in (0..times.len()).rev() {
+		offsets[sub_index] = offset_at(offset_index);
+		if switched[sub_index][offset_index] {
+			offset_index = bests[sub_index];
+		}
+	}
+	(score, offsets)
+}
+
+// Least squares fit of the points (x, y)
+fn fit_linear(points: &[(i64, i64)], default_scale: f64) -> Linear {
+	let count = points.len() as f64;
+	let mean_x = points.iter().map(|&(x, _)| x as f64).sum::<f64>() / count;
+	let mean_y = points.iter().map(|&(_, y)| y as f64).sum::<f64>() / count;
+	let mut cov = 0.0;
+	let mut var = 0.0;
+	for &(x, y) in points.iter() {
+		cov += (x as f64 - mean_x) * (y as f64 - mean_y);
+		var += (x as f64 - mean_x) * (x as f64 - mean_x);
+	}
+	let scale = if var > 0.0 { cov / var } else { default_scale };
+	Linear { scale, offset: mean_y - scale * mean_x }
+}
+
+// A run of subtitles corrected by the same transform
+#[derive(Debug)]
+pub struct Segment {
+	pub first_num: u32,
+	pub last_num: u32,
+	pub linear: Linear
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Retimes subtitles after a correctly timed reference of the same video,
+// for example in another language. The subtitles are matched with the
+// reference by their timings, allowing the offset to change at some points
+// (commercial breaks), then a linear correction is fitted between these
+// points.
+pub fn sync_to_reference(subtitles: &mut [Subtitle], reference: &[Subtitle])
+	-> Result<Vec<Segment>,SyncError> {
+
+	let coverage = Coverage::new(reference);
+	if coverage.intervals.is_empty() {
+		return Err(SyncError::EmptyReference);
+	}
+	if subtitles.is_empty() {
+		return Ok(Vec::new());
+	}
+
+	let mut order: Vec<usize> = (0..subtitles.len()).collect();
+	order.sort_by_key(|&index| subtitles[index].time_from);
+
+	let scaled_times = |scale: (i64, i64)| -> Vec<(i64, i64)> {
+		order.iter().map(|&index| {
+			let subtitle = &subtitles[index];
+			(subtitle.time_from as i64 * scale.0 / scale.1,
+				subtitle.time_to as i64 * scale.0 / scale.1)
+		}).collect()
+	};
+
+	// Choose the frame rate ratio first
+	let mut best_scale = SCALES[0];
+	let mut best_score = i64::MIN;
+	for &scale in SCALES.iter() {
+		let (score, _) = best_offsets(&scaled_times(scale), &coverage, false);
+		if score > best_score {
+			best_score = score;
+			best_scale = scale;
+		}
+	}
+	let times = scaled_times(best_scale);
+	let (_, offsets) = best_offsets(&times, &coverage, true);
+
+	// Cut in segments at the breaks, then fit each segment
+	let mut segments: Vec<Segment> = Vec::new();
+	let mut start = 0;
+	for end in 1..(order.len() + 1) {
+		if end < order.len() && (offsets[end] - offsets[end - 1]).abs() < BREAK_MIN {
+			continue;
+		}
+		let points: Vec<(i64, i64)> = (start..end).map(|pos| {
+			(subtitles[order[pos]].time_from as i64, times[pos].0 + offsets[pos])
+		}).collect();
+		let linear = fit_linear(&points, best_scale.0 as f64 / best_scale.1 as f64);
+		for &index in order[start..end].iter() {
+			let subtitle = &mut subtitles[index];
+			subtitle.time_from = linear.apply(subtitle.time_from);
+			subtitle.time_to = linear.apply(subtitle.time_to);
+		}
+		segments.push(Segment {
+			first_num: subtitles[order[start]].num,
+			last_num: subtitles[order[end - 1]].num,
+			linear
+		});
+		start = end;
+	}
+	Ok(segments)
+}
+
+#[test]
+fn test_sync_to_reference() {
+	// Irregular subtitles, like real ones
+	let mut seed: u32 = 42;
+	let mut random = |max: i32| {
+		seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+		((seed >> 8) % max as u32) as i32
+	};
+	let mut reference: Vec<Subtitle> = Vec::new();
+	let mut time = 5_000;
+	for num in 1..81 {
+		let duration = 1_000 + random(3_000);
+		reference.push(Subtitle { num, time_from: time, time_to: time + duration, ..Default::default() });
+		time += duration + 200 + random(6_000);
+	}
+
+	// Late by 2 s, then by 32 s after a commercial break
+	let mut subs: Vec<Subtitle> = reference.iter().map(|subtitle| {
+		let offset = if subtitle.num <= 40 { 2_000 } else { 32_000 };
+		Subtitle { num: subtitle.num,
+			time_from: subtitle.time_from + offset,
+			time_to: subtitle.time_to + offset,
+			..Default::default() }
+	}).collect();
+	let res = sync_to_reference(&mut subs, &reference);
+	assert!(res.is_ok(), "{}", res.err().unwrap());
+	let segments = res.unwrap();
+	assert_eq!(segments.len(), 2);
+	assert_eq!(segments[0].first_num, 1);
+	assert_eq!(segments[0].last_num, 40);
+	for (subtitle, ref_subtitle) in subs.iter().zip(reference.iter()) {
+		assert_eq!(subtitle.time_from, ref_subtitle.time_from);
+		assert_eq!(subtitle.time_to, ref_subtitle.time_to);
+	}
+
+	// Made for 25 fps, played at 23.976 fps
+	let fps_linear = Linear { scale: 24000.0 / 25025.0, offset: 300.0 };
+	let mut subs: Vec<Subtitle> = reference.iter().map(|subtitle| {
+		Subtitle { num: subtitle.num,
+			time_from: fps_linear.apply(subtitle.time_from),
+			time_to: fps_linear.apply(subtitle.time_to),
+			..Default::default() }
+	}).collect();
+	let segments = sync_to_reference(&mut subs, &reference).unwrap();
+	assert_eq!(segments.len(), 1);
+	for (subtitle, ref_subtitle) in subs.iter().zip(reference.iter()) {
+		assert!((subtitle.time_from - ref_subtitle.time_from).abs() <= 60,
+			"{} {}", subtitle.time_from, ref_subtitle.time_from);
+	}
+
+	assert!(sync_to_reference(&mut subs, &[]).is_err());
+}
@@ -1,4 +1,5 @@
-use rules;
+use rules::RuleSet;
+use srt::Subtitle;
 
 fn is_separator(c: char) -> bool {
 	return c == ' ' || c == '\u{A0}'
@@ -143,29 +144,259 @@ fn replace_by_rule(line_str: &str, what: &str, with: &str) -> String {
 	new_line
 }
 
-///////////////////////////////////////////////////////////////////////////////
-// Replaces words in one line of text based on rules
-pub fn replace_one(text: &str, language: &str) -> String {
+// A rule that changed a text: what, with
+pub type FiredRule = (String, String);
+
+// Replaces words in a piece of text without override blocks
+fn replace_in_run(text: &str, rule_set: &RuleSet, fired: &mut Vec<FiredRule>) -> String {
 	let mut result = text.to_string();
 
-	let rule_set = if language == "en" {
-		rules::RULES_EN
+	for rule in rule_set.rules.iter() {
+		let new_result = match rule.regex {
+			Some(ref regex) => regex.replace_all(&result, &rule.with[..]).into_owned(),
+			None => replace_by_rule(&result, &rule.what, &rule.with)
+		};
+		if new_result != result {
+			let fired_rule = (rule.what.clone(), rule.with.clone());
+			if !fired.contains(&fired_rule) {
+				fired.push(fired_rule);
+			}
+			result = new_result;
+		}
+	}
+	result
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Replaces words in one line of text based on rules, and tells which rules
+// changed it.
+// Override blocks like {\i1} (ASS, sometimes found in SRT) and tags like
+// <font color="red"> are left untouched.
+pub fn replace_one_traced(text: &str, rule_set: &RuleSet) -> (String, Vec<FiredRule>) {
+	let mut result = String::new();
+	let mut fired: Vec<FiredRule> = Vec::new();
+	let mut rest = text;
+	loop {
+		// The first block closed before the next one opens
+		let block = rest.char_indices().filter_map(|(start, c)| {
+			let close = match c {
+				'{' => '}',
+				'<' => '>',
+				_ => return None
+			};
+			rest[start..].find(close)
+				.filter(|&len| !rest[start + 1..start + len].contains(c))
+				.map(|len| (start, start + len + 1))
+		}).next();
+		match block {
+			Some((start, stop)) => {
+				if start > 0 {
+					result.push_str(&replace_in_run(&rest[..start], rule_set, &mut fired));
+				}
+				result.push_str(&rest[start..stop]);
+				rest = &rest[stop..];
+			},
+			None => {
+				if !rest.is_empty() {
+					result.push_str(&replace_in_run(rest, rule_set, &mut fired));
+				}
+				break;
+			}
+		}
+	}
+	(result, fired)
+}
+
+#[test]
+fn test_replace_one_languages() {
+	let es = RuleSet::builtin("es");
+	assert_eq!(replace_one("Que tal, amigo?", &es), "Qué tal, amigo?");
+	let de = RuleSet::builtin("de");
+	assert_eq!(replace_one("Er sagt \"Hallo\", z.B. so", &de), "Er sagt „Hallo“, z. B. so");
+	assert_eq!(replace_one("Birnen usw und mehr", &de), "Birnen usw. und mehr");
+	assert_eq!(replace_one("Birnen usw. und Äpfel usw", &de), "Birnen usw. und Äpfel usw.");
+	assert_eq!(replace_one("<font color=\"red\">Er sagt \"Hallo\"</font>", &de),
+		"<font color=\"red\">Er sagt „Hallo“</font>");
+	assert_eq!(replace_one("a < \"b\" <i>c</i>", &de), "a < „b“ <i>c</i>");
+	let it = RuleSet::builtin("it");
+	assert_eq!(replace_one("E' cosi, perchè no", &it), "È così, perché no");
+	let pt = RuleSet::builtin("pt");
+	assert_eq!(replace_one("Voce nao tem informacao", &pt), "Você não tem informação");
+	assert_eq!(replace_one("Situacoes da Nacao", &pt), "Situações da Nação");
+	assert_eq!(replace_one("O Falcao e o cao", &pt), "O Falcao e o cao");
+	assert!(RuleSet::builtin("xx").rules.is_empty());
+}
+
+#[test]
+fn test_replace_one_traced() {
+	let (result, fired) = replace_one_traced("Ca va bien?", &RuleSet::builtin("fr"));
+	assert_eq!(result, "Ça va bien\u{A0}?");
+	assert_eq!(fired, vec![("+?".to_string(), "\u{A0}?".to_string()), ("Ca".to_string(), "Ça".to_string())]);
+	assert!(replace_one_traced("Fine", &RuleSet::builtin("en")).1.is_empty());
+}
+
+// Words that start a question or an exclamation after a comma
+const QUESTION_WORDS_ES: &[&str] = &["qué", "que", "quién", "quien", "quiénes", "cómo", "como",
+	"dónde", "donde", "adónde", "cuándo", "cuando", "cuánto", "cuanto", "cuántos", "cuántas",
+	"cuál", "cual", "cuáles", "verdad"];
+
+// A sentence ends before the character at index: after a final punctuation
+// mark, or at the end of a line when the next one is a dialogue line
+fn ends_sentence(text: &[char], index: usize) -> bool {
+	match text[index - 1] {
+		'.' | '?' | '!' | '…' => true,
+		'\n' => text[index..].iter().find(|c| !c.is_whitespace()) == Some(&'-'),
+		_ => false
+	}
+}
+
+// Index of the first letter of a clause, after spaces, dialogue dashes, tags
+// and quotes
+fn first_letter(text: &[char], start: usize, end: usize) -> Option<usize> {
+	let mut index = start;
+	while index < end {
+		let c = text[index];
+		if c == '<' || c == '{' {
+			let close = if c == '<' { '>' } else { '}' };
+			index = text[index..end].iter().position(|&other| other == close).map(|len| index + len)?;
+		}
+		else if c.is_alphanumeric() {
+			return Some(index);
+		}
+		else if !(c.is_whitespace() || c == '-' || c == '"' || c == '«') {
+			return None;
+		}
+		index += 1;
+	}
+	None
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Adds the ¿ and ¡ that open the questions and exclamations of the lines of
+// a subtitle. A question starts at the start of its sentence, which may be
+// on a previous line, after a colon, or after a comma followed by a question
+// word. A subtitle starting in lower case continues a sentence of the
+// previous one, so its first sentence is left as is.
+pub fn add_inverted_marks(texts: &[String]) -> Vec<String> {
+	let mut text: Vec<char> = texts.join("\n").chars().collect();
+	let mut inserts: Vec<(usize, char)> = Vec::new();
+
+	for end in 0..text.len() {
+		let mark = match text[end] {
+			'?' => '¿',
+			'!' => '¡',
+			_ => continue
+		};
+		let mut start = end;
+		while start > 0 && !ends_sentence(&text, start) {
+			start -= 1;
+		}
+		if text[start..end].contains(&mark) {
+			continue;
+		}
+
+		let word_at = |index: usize| -> String {
+			text[index..end].iter().take_while(|c| c.is_alphabetic()).collect::<String>().to_lowercase()
+		};
+		let mut clause = start;
+		for index in (start..end).rev() {
+			if text[index] == ':' {
+				clause = index + 1;
+				break;
+			}
+			if text[index] == ',' {
+				if let Some(letter) = first_letter(&text, index + 1, end) {
+					if QUESTION_WORDS_ES.contains(&word_at(letter).as_str()) {
+						clause = index + 1;
+						break;
+					}
+				}
+			}
+		}
+		let letter = match first_letter(&text, clause, end) {
+			Some(letter) => letter,
+			None => continue
+		};
+		if clause == 0 && text[letter].is_lowercase() {
+			continue;
+		}
+		inserts.push((letter, mark));
+	}
+
+	for &(index, mark) in inserts.iter().rev() {
+		text.insert(index, mark);
 	}
-	else {
-		rules::RULES_FR
+	text.into_iter().collect::<String>().split('\n').map(|line| line.to_string()).collect()
+}
+
+#[test]
+fn test_add_inverted_marks() {
+	let marks = |texts: &[&str]| -> Vec<String> {
+		add_inverted_marks(&texts.iter().map(|text| text.to_string()).collect::<Vec<String>>())
 	};
+	assert_eq!(marks(&["Qué tal, amigo?"]), vec!["¿Qué tal, amigo?"]);
+	assert_eq!(marks(&["- Hola. Vienes?"]), vec!["- Hola. ¿Vienes?"]);
+	assert_eq!(marks(&["¿Vienes? Genial!"]), vec!["¿Vienes? ¡Genial!"]);
+	assert_eq!(marks(&["Dijo: Vienes?"]), vec!["Dijo: ¿Vienes?"]);
+	assert_eq!(marks(&["Bueno, cómo estás?"]), vec!["Bueno, ¿cómo estás?"]);
+	assert_eq!(marks(&["Me dices por qué", "no vienes?"]), vec!["¿Me dices por qué", "no vienes?"]);
+	assert_eq!(marks(&["- Vienes?", "- <i>Claro!</i>"]), vec!["- ¿Vienes?", "- <i>¡Claro!</i>"]);
+	assert_eq!(marks(&["¿Vienes, o no?"]), vec!["¿Vienes, o no?"]);
+	// Continues the previous subtitle
+	assert_eq!(marks(&["y tu?"]), vec!["y tu?"]);
+	assert_eq!(marks(&["y tu? Vienes?"]), vec!["y tu? ¿Vienes?"]);
+}
 
-	for rule_ref in rule_set.iter() {
-		let &(what, with) = rule_ref;
-		result = replace_by_rule(&result, what, with);
+///////////////////////////////////////////////////////////////////////////////
+// Replaces words in the lines of a subtitle, and tells which rules changed
+// each line. Unlike the rules, the inverted marks need all the lines.
+pub fn replace_texts_traced(texts: &[String], rule_set: &RuleSet) -> Vec<(String, Vec<FiredRule>)> {
+	let mut lines: Vec<(String, Vec<FiredRule>)> = texts.iter()
+		.map(|text| replace_one_traced(text, rule_set))
+		.collect();
+	if rule_set.inverted_marks {
+		let replaced: Vec<String> = lines.iter().map(|line| line.0.clone()).collect();
+		for (line, marked) in lines.iter_mut().zip(add_inverted_marks(&replaced)) {
+			for &(mark, what, with) in [('¿', "?", "¿?"), ('¡', "!", "¡!")].iter() {
+				if marked.matches(mark).count() > line.0.matches(mark).count() {
+					line.1.push((what.to_string(), with.to_string()));
+				}
+			}
+			line.0 = marked;
+		}
+	}
+	lines
+}
+
+#[test]
+fn test_replace_texts_traced() {
+	let texts = vec!["Que tal , amigo?".to_string()];
+	let lines = replace_texts_traced(&texts, &RuleSet::builtin("es"));
+	assert_eq!(lines[0].0, "¿Qué tal, amigo?");
+	assert_eq!(lines[0].1.last(), Some(&("?".to_string(), "¿?".to_string())));
+	assert_eq!(replace_texts_traced(&texts, &RuleSet::builtin("en"))[0].0, "Que tal, amigo?");
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Replaces words in one line of text based on rules.
+pub fn replace_one(text: &str, rule_set: &RuleSet) -> String {
+	replace_one_traced(text, rule_set).0
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Replaces words in all the lines of the subtitles
+pub fn replace_subtitles(subtitles: &mut [Subtitle], rule_set: &RuleSet) {
+	for subtitle in subtitles.iter_mut() {
+		subtitle.texts = replace_texts_traced(&subtitle.texts, rule_set).into_iter()
+			.map(|(text, _)| text)
+			.collect();
 	}
-	result
 }
 
 // For unit testing
 #[allow(dead_code)]
 pub fn replace_one_fr(text: &str) -> String {
-	replace_one(text, "fr")
+	replace_one(text, &RuleSet::builtin("fr"))
 }
 
 #[test]
@@ -197,7 +428,13 @@ fn test_replace_one_fr() {
 	assert_eq!(replace_one_fr("10ème"), "10ᵉ");
 	assert_eq!(replace_one_fr("10e"), "10ᵉ");
 	assert_eq!(replace_one_fr("10è"), "10ᵉ");
+	assert_eq!(replace_one_fr("le 2ème, le 21e."), "le 2ᵉ, le 21ᵉ.");
+	assert_eq!(replace_one_fr("1ème 2èmes"), "1ème 2èmes");
 	assert_eq!(replace_one_fr("\"Oeil pour oeil\""), "\"Œil pour œil\"");
 	assert_eq!(replace_one_fr("Etaient-ils"), "Étaient-ils");
 	assert_eq!(replace_one_fr("caca"), "caca");
+
+	// Override blocks are not modified
+	assert_eq!(replace_one_fr("{\\i1}Ca va{\\i0}"), "{\\i1}Ça va{\\i0}");
+	assert_eq!(replace_one_fr("{\\fnArial  Black}A demain"), "{\\fnArial  Black}À demain");
 }
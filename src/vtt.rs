@@ -0,0 +1,243 @@
+use srt;
+use srt::{Format, Subtitle, SubtitleFile};
+use error::ParseError;
+
+// Returns true if a block starting with this line is not a cue
+fn is_note_block(first_line: &str) -> bool {
+	for keyword in &["NOTE", "STYLE", "REGION"] {
+		if let Some(rest) = first_line.strip_prefix(keyword) {
+			if rest.is_empty() || rest.starts_with(' ') || rest.starts_with('\t') {
+				return true;
+			}
+		}
+	}
+	false
+}
+
+// Parses a cue timing line: "00:01.000 --> 00:02.000 align:start"
+fn parse_timing(subtitle: &mut Subtitle, line: &str, line_num: usize) -> Result<(),ParseError> {
+	let arrow_index = match line.find("-->") {
+		Some(index) => index,
+		None => {
+			return Err(ParseError::BadDurationSeparator { line_num, text: line.to_string() });
+		}
+	};
+	let left = line[0..arrow_index].trim();
+	let right = line[arrow_index + 3..].trim();
+
+	// The end time may be followed by cue settings
+	let (right_time, settings) = match right.find(char::is_whitespace) {
+		Some(index) => (&right[..index], right[index..].trim()),
+		None => (right, "")
+	};
+
+	subtitle.time_from = match srt::parse_srt_time(left) {
+		Some(milli) => milli,
+		None => {
+			return Err(ParseError::BadTimeFrom { line_num, text: left.to_string() });
+		}
+	};
+	subtitle.time_to = match srt::parse_srt_time(right_time) {
+		Some(milli) => milli,
+		None => {
+			return Err(ParseError::BadTimeTo { line_num, text: right_time.to_string() });
+		}
+	};
+	subtitle.settings = settings.to_string();
+	Ok(())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Parses a WebVTT file. Blocks are separated by empty lines: the header,
+// then cues, NOTE, STYLE and REGION blocks.
+pub fn parse_vtt(content: &str) -> Result<SubtitleFile,ParseError> {
+	let mut file = SubtitleFile::new(Format::Vtt);
+
+	// Group lines in blocks, remembering the line number of each block
+	let mut blocks: Vec<(usize, Vec<&str>)> = Vec::new();
+	let mut current: Vec<&str> = Vec::new();
+	let mut current_start = 1;
+	for (line_index, line_ori) in content.lines().enumerate() {
+		let line = line_ori.trim_end();
+		if line.is_empty() {
+			if !current.is_empty() {
+				blocks.push((current_start, current));
+				current = Vec::new();
+			}
+		}
+		else {
+			if current.is_empty() {
+				current_start = line_index + 1;
+			}
+			current.push(line);
+		}
+	}
+	if !current.is_empty() {
+		blocks.push((current_start, current));
+	}
+
+	let mut block_iter = blocks.into_iter();
+	match block_iter.next() {
+		Some((_, header)) => {
+			if !header[0].starts_with("WEBVTT") {
+				return Err(ParseError::MissingVttHeader(Some(header[0].to_string())));
+			}
+			file.header = header.iter().map(|line| line.to_string()).collect();
+		},
+		None => {
+			return Err(ParseError::MissingVttHeader(None));
+		}
+	}
+
+	let mut notes: Vec<String> = Vec::new();
+	let mut num = 1;
+	for (line_num, lines) in block_iter {
+		if is_note_block(lines[0]) {
+			notes.push(lines.join("\n"));
+			continue;
+		}
+
+		let mut subtitle = Subtitle { num, ..Default::default() };
+
+		// The timing line may be preceded by an identifier
+		let timing_index = if lines[0].contains("-->") {
+			0
+		}
+		else {
+			subtitle.ident = lines[0].to_string();
+			1
+		};
+		if timing_index >= lines.len() {
+			return Err(ParseError::MissingCueTiming { line_num: line_num + 1 });
+		}
+		parse_timing(&mut subtitle, lines[timing_index], line_num + timing_index)?;
+		subtitle.line_num = (line_num + timing_index + 1) as u32;
+
+		for line in lines.iter().skip(timing_index + 1) {
+			subtitle.push_text(line);
+		}
+
+		subtitle.notes = notes;
+		notes = Vec::new();
+		file.subtitles.push(subtitle);
+		num += 1;
+	}
+	file.notes = notes;
+	Ok(file)
+}
+
+#[test]
+fn test_parse_vtt() {
+	let vtt = "WEBVTT - Some title\n\
+Kind: captions\n\
+\n\
+STYLE\n\
+::cue { color: yellow }\n\
+\n\
+1\n\
+00:00:16.087 --> 00:00:19.911 align:start line:0\n\
+hello\n\
+mister\n\
+\n\
+NOTE this is\n\
+a comment\n\
+\n\
+01:02.500 --> 01:03.000\n\
+<v Bob>hi\n\
+\n\
+NOTE the end\n";
+	let file_res = parse_vtt(vtt);
+	assert!(file_res.is_ok(), "{}", file_res.err().unwrap());
+	let file = file_res.unwrap();
+	assert_eq!(file.header, vec!["WEBVTT - Some title", "Kind: captions"]);
+	assert_eq!(file.subtitles.len(), 2);
+
+	let sub = &file.subtitles[0];
+	assert_eq!(sub.ident, "1");
+	assert_eq!(sub.time_from, 16_087);
+	assert_eq!(sub.time_to, 19_911);
+	assert_eq!(sub.settings, "align:start line:0");
+	assert_eq!(sub.texts.len(), 2);
+	assert_eq!(sub.texts[1], "mister");
+	assert_eq!(sub.line_num, 9);
+	assert_eq!(sub.notes, vec!["STYLE\n::cue { color: yellow }"]);
+
+	let sub = &file.subtitles[1];
+	assert_eq!(sub.ident, "");
+	assert_eq!(sub.time_from, 62_500);
+	assert_eq!(sub.settings, "");
+	assert_eq!(sub.texts[0], "<v Bob>hi");
+	assert_eq!(sub.notes, vec!["NOTE this is\na comment"]);
+
+	assert_eq!(file.notes, vec!["NOTE the end"]);
+
+	assert!(parse_vtt("1\n00:00:01,000 --> 00:00:02,000\nhi\n").is_err());
+}
+
+///////////////////////////////////////////////////////////////////////////////
+pub fn format_vtt_time_from_milli(milli: i32) -> String {
+	srt::format_srt_time_from_milli(milli).replace(',', ".")
+}
+
+// Pushes a block verbatim, with the lines separated by CRLF
+fn push_block(ret: &mut String, block: &str) {
+	for line in block.lines() {
+		ret.push_str(line);
+		ret.push_str("\r\n");
+	}
+	ret.push_str("\r\n");
+}
+
+///////////////////////////////////////////////////////////////////////////////
+pub fn format_vtt(file: &SubtitleFile) -> String {
+	let mut ret = String::new();
+	if file.header.is_empty() {
+		// Converted from another format
+		ret.push_str("WEBVTT\r\n\r\n");
+	}
+	else {
+		push_block(&mut ret, &file.header.join("\n"));
+	}
+
+	for subtitle in file.subtitles.iter() {
+		for note in subtitle.notes.iter() {
+			push_block(&mut ret, note);
+		}
+		if !subtitle.ident.is_empty() {
+			ret.push_str(&subtitle.ident);
+			ret.push_str("\r\n");
+		}
+		ret.push_str(&format_vtt_time_from_milli(subtitle.time_from));
+		ret.push_str(" --> ");
+		ret.push_str(&format_vtt_time_from_milli(subtitle.time_to));
+		if !subtitle.settings.is_empty() {
+			ret.push(' ');
+			ret.push_str(&subtitle.settings);
+		}
+		ret.push_str("\r\n");
+		for text in subtitle.texts.iter() {
+			ret.push_str(text);
+			ret.push_str("\r\n");
+		}
+		ret.push_str("\r\n");
+	}
+
+	for note in file.notes.iter() {
+		push_block(&mut ret, note);
+	}
+	ret
+}
+
+#[test]
+fn test_format_vtt() {
+	let vtt = "WEBVTT\r\n\r\n\
+NOTE hello\r\n\r\n\
+intro\r\n\
+00:00:01.000 --> 00:00:02.500 position:10% align:start\r\n\
+- Hi\r\n\
+- Hello\r\n\r\n\
+01:00:00.000 --> 01:00:01.000\r\n\
+bye\r\n\r\n";
+	let file = parse_vtt(vtt).unwrap();
+	assert_eq!(format_vtt(&file), vtt);
+}
@@ -0,0 +1,211 @@
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use srt::Format;
+use error::WalkError;
+
+///////////////////////////////////////////////////////////////////////////////
+// Matches a file name against a shell like pattern: '*' matches any number
+// of characters, '?' matches one character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+	let pattern: Vec<char> = pattern.chars().collect();
+	let name: Vec<char> = name.chars().collect();
+	let mut pi = 0;
+	let mut ni = 0;
+	// Where to go back when a character does not match after a '*'
+	let mut star: Option<(usize, usize)> = None;
+
+	while ni < name.len() {
+		if pi < pattern.len() && (pattern[pi] == '?' || pattern[pi] == name[ni]) {
+			pi += 1;
+			ni += 1;
+		}
+		else if pi < pattern.len() && pattern[pi] == '*' {
+			star = Some((pi, ni));
+			pi += 1;
+		}
+		else if let Some((star_pi, star_ni)) = star {
+			// Let the '*' match one more character
+			pi = star_pi + 1;
+			ni = star_ni + 1;
+			star = Some((star_pi, star_ni + 1));
+		}
+		else {
+			return false;
+		}
+	}
+	pattern[pi..].iter().all(|&c| c == '*')
+}
+
+#[test]
+fn test_glob_match() {
+	assert!(glob_match("*.fr.srt", "s01e01.fr.srt"));
+	assert!(!glob_match("*.fr.srt", "s01e01.en.srt"));
+	assert!(glob_match("s01e0?.*", "s01e05.vtt"));
+	assert!(!glob_match("s01e0?.*", "s01e10.vtt"));
+	assert!(glob_match("*", ""));
+	assert!(glob_match("a*b*c", "aXbYbZc"));
+	assert!(!glob_match("a*b*c", "aXbYbZ"));
+	assert!(glob_match("épisode*", "épisode 1.srt"));
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Tells if a .sub file is a VobSub one, made of images, rather than a MicroDVD
+// one: it has a .idx file next to it, or it is not text.
+pub fn is_vobsub(file_path: &str) -> bool {
+	if Format::from_path(file_path) != Some(Format::MicroDvd) {
+		return false;
+	}
+	let path = Path::new(file_path);
+	if path.with_extension("idx").is_file() || path.with_extension("IDX").is_file() {
+		return true;
+	}
+	let mut head = [0u8; 512];
+	match fs::File::open(path).and_then(|mut file| file.read(&mut head)) {
+		Ok(len) => head[..len].contains(&0),
+		Err(_) => false
+	}
+}
+
+#[test]
+fn test_is_vobsub() {
+	let dir = std::env::temp_dir().join(format!("fixsrt_vobsub_{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+	fs::write(path("text.sub"), "{0}{25}Hello\n").unwrap();
+	fs::write(path("image.sub"), [0x00, 0x00, 0x01, 0xBA, 0x44]).unwrap();
+	fs::write(path("indexed.sub"), "{0}{25}Hello\n").unwrap();
+	fs::write(path("indexed.idx"), "# VobSub index file, v7\n").unwrap();
+	assert!(!is_vobsub(&path("text.sub")));
+	assert!(is_vobsub(&path("image.sub")));
+	assert!(is_vobsub(&path("indexed.sub")));
+	assert!(!is_vobsub(&path("indexed.idx")));
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+// What to do with a file found in a directory
+#[derive(Debug, PartialEq)]
+pub enum Choice {
+	// To be processed
+	Wanted,
+	// A subtitle file left out: backup, working file, excluded or VobSub
+	Skipped,
+	// Not a subtitle file
+	Other
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Chooses if a file found in a directory is to be processed. Backups and
+// working files made by fixsrt are never taken. Without include pattern, the
+// extension must be a known subtitle format.
+pub fn choose_file(file_path: &str, include: &[&str], exclude: &[&str]) -> Choice {
+	for suffix in ["~", ".work"].iter() {
+		if let Some(original) = file_path.strip_suffix(suffix) {
+			// Counted as skipped only when the original file would be taken
+			return match choose_file(original, include, exclude) {
+				Choice::Other => Choice::Other,
+				_ => Choice::Skipped
+			};
+		}
+	}
+	let name = match Path::new(file_path).file_name() {
+		Some(name) => name.to_string_lossy(),
+		None => return Choice::Other
+	};
+	let included = if include.is_empty() {
+		Format::from_path(file_path).is_some()
+	}
+	else {
+		include.iter().any(|pattern| glob_match(pattern, &name))
+	};
+	if !included {
+		Choice::Other
+	}
+	else if exclude.iter().any(|pattern| glob_match(pattern, &name)) || is_vobsub(file_path) {
+		Choice::Skipped
+	}
+	else {
+		Choice::Wanted
+	}
+}
+
+#[test]
+fn test_choose_file() {
+	assert_eq!(choose_file("season1/e01.srt", &[], &[]), Choice::Wanted);
+	assert_eq!(choose_file("season1/e01.ass", &[], &[]), Choice::Wanted);
+	assert_eq!(choose_file("season1/e01.srt~", &[], &[]), Choice::Skipped);
+	assert_eq!(choose_file("season1/e01.srt.work", &[], &[]), Choice::Skipped);
+	assert_eq!(choose_file("season1/e01.nfo", &[], &[]), Choice::Other);
+	assert_eq!(choose_file("season1/e01.nfo~", &[], &[]), Choice::Other);
+	assert_eq!(choose_file("season1/e01.fr.srt", &["*.fr.srt"], &[]), Choice::Wanted);
+	assert_eq!(choose_file("season1/e01.en.srt", &["*.fr.srt"], &[]), Choice::Other);
+	assert_eq!(choose_file("season1/e01.srt~", &["*"], &[]), Choice::Skipped);
+	assert_eq!(choose_file("season1/e01.txt", &["*.txt"], &[]), Choice::Wanted);
+	assert_eq!(choose_file("season1/e01.forced.srt", &[], &["*.forced.*"]), Choice::Skipped);
+}
+
+// Adds the wanted files of a directory and of its subdirectories, sorted by
+// name, and the skipped ones
+fn walk_dir(dir_path: &Path, include: &[&str], exclude: &[&str],
+	found: &mut FoundFiles) {
+	let walk_error = |error| WalkError { path: dir_path.to_string_lossy().into_owned(), error };
+	let entries = match fs::read_dir(dir_path) {
+		Ok(entries) => entries,
+		Err(error) => {
+			found.errors.push(walk_error(error));
+			return;
+		}
+	};
+	let mut entries: Vec<fs::DirEntry> = match entries.collect() {
+		Ok(entries) => entries,
+		Err(error) => {
+			found.errors.push(walk_error(error));
+			return;
+		}
+	};
+	entries.sort_by_key(|entry| entry.file_name());
+
+	for entry in entries {
+		let path = entry.path();
+		// Symbolic links to directories are not followed, to avoid loops
+		if entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false) {
+			walk_dir(&path, include, exclude, found);
+		}
+		else if path.is_file() {
+			let file_path = path.to_string_lossy().into_owned();
+			match choose_file(&file_path, include, exclude) {
+				Choice::Wanted => found.files.push(file_path),
+				Choice::Skipped => found.skipped.push(file_path),
+				Choice::Other => ()
+			}
+		}
+	}
+}
+
+// What a walk found
+#[derive(Default)]
+pub struct FoundFiles {
+	// The files to process
+	pub files: Vec<String>,
+	// The subtitle files left out
+	pub skipped: Vec<String>,
+	// The directories that could not be read
+	pub errors: Vec<WalkError>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Replaces the directories of a list of paths by the wanted files they
+// contain, recursively. Other paths are kept as is.
+pub fn find_files(paths: &[&str], include: &[&str], exclude: &[&str]) -> FoundFiles {
+	let mut found = FoundFiles::default();
+	for path in paths.iter() {
+		if Path::new(path).is_dir() {
+			walk_dir(Path::new(path), include, exclude, &mut found);
+		}
+		else {
+			found.files.push(path.to_string());
+		}
+	}
+	found
+}
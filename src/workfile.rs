@@ -35,13 +35,16 @@ impl WorkFile {
 		ret
 	}
 
-	pub fn commit(&mut self) {
+	// Replaces the final file by the working file. On error, the working
+	// file is deleted.
+	pub fn commit(&mut self) -> io::Result<()> {
 		let file = self.file.take();
 		drop(file);
-		match std::fs::rename(&self.work_file_path, &self.file_path) {
-			Ok(_) => (),
-			Err(err) => panic!("commit failed: {}", err)
+		let ret = std::fs::rename(&self.work_file_path, &self.file_path);
+		if ret.is_err() {
+			let _ = std::fs::remove_file(&self.work_file_path);
 		}
+		ret
 	}
 }
 
@@ -49,10 +52,8 @@ impl Drop for WorkFile {
 	fn drop(&mut self) {
 		if self.file.is_some() {
 			drop(self.file.take());
-			match std::fs::remove_file(&self.work_file_path) {
-				Ok(_) => (),
-				Err(err) => panic!("rollback failed: {}", err)
-			}
+			// Nothing more can be done if it fails
+			let _ = std::fs::remove_file(&self.work_file_path);
 		}
 	}
 }
@@ -0,0 +1,315 @@
+use srt::Subtitle;
+use reading;
+
+// Words a line can start with, in the supported languages
+const CONJUNCTIONS: &[&str] = &[
+	"and", "but", "or", "because", "so",
+	"et", "mais", "ou", "donc", "car", "parce", "que", "qui",
+	"y", "o", "pero", "porque",
+	"und", "aber", "oder", "weil", "dass",
+	"e", "ma", "perché",
+	"mas"
+];
+
+// Words a line should not end with: articles, prepositions
+const WEAK_WORDS: &[&str] = &[
+	"the", "a", "an", "to", "of", "in", "on", "at", "for", "with", "from", "by",
+	"le", "la", "les", "un", "une", "des", "du", "de", "au", "aux", "à", "avec", "pour", "par", "sur", "dans", "en",
+	"el", "los", "las", "una", "del", "al", "con", "por", "para",
+	"der", "die", "das", "den", "dem", "ein", "eine", "einen", "mit", "von", "zu", "für",
+	"il", "lo", "gli", "di", "della", "per",
+	"o", "os", "as", "um", "uma", "do", "da", "com", "em"
+];
+
+// Cost of a break, compared to the length differences of the lines, in
+// characters
+const BREAK_AFTER_PUNCTUATION: i64 = 0;
+const BREAK_BEFORE_CONJUNCTION: i64 = 10;
+const BREAK_ELSEWHERE: i64 = 25;
+const BREAK_AFTER_WEAK_WORD: i64 = 60;
+
+// Characters shown on screen
+fn visible_len(text: &str) -> usize {
+	reading::strip_tags(text).chars().count()
+}
+
+// Splits a text at the spaces that are not inside tag markup like
+// <font color="red">. No-break spaces do not split.
+fn split_words(text: &str) -> Vec<&str> {
+	let mut words = Vec::new();
+	let mut start = 0;
+	let mut closing: Option<char> = None;
+	for (index, c) in text.char_indices() {
+		match closing {
+			Some(close) => if c == close {
+				closing = None;
+			},
+			None => match c {
+				'<' => closing = Some('>'),
+				'{' => closing = Some('}'),
+				' ' => {
+					if index > start {
+						words.push(&text[start..index]);
+					}
+					start = index + 1;
+				},
+				_ => ()
+			}
+		}
+	}
+	if text.len() > start {
+		words.push(&text[start..]);
+	}
+	words
+}
+
+// Cost of breaking the line between two words
+fn break_cost(before: &str, after: &str) -> i64 {
+	let before = reading::strip_tags(before);
+	let after = reading::strip_tags(after).to_lowercase();
+	if before.ends_with(|c: char| ".,;:!?…".contains(c)) {
+		BREAK_AFTER_PUNCTUATION
+	}
+	else if WEAK_WORDS.contains(&before.to_lowercase().as_str()) {
+		BREAK_AFTER_WEAK_WORD
+	}
+	else if CONJUNCTIONS.contains(&after.as_str()) {
+		BREAK_BEFORE_CONJUNCTION
+	}
+	else {
+		BREAK_ELSEWHERE
+	}
+}
+
+// Best split of the words in line_count lines of at most max_chars, as the
+// index of the first word of each line after the first one
+fn best_breaks(words: &[&str], lens: &[usize], line_count: usize, max_chars: usize) -> Option<Vec<usize>> {
+	let word_count = words.len();
+	let total: usize = lens.iter().sum::<usize>() + word_count - 1;
+	let target = total as i64 / line_count as i64;
+	let line_len = |first: usize, end: usize| -> usize {
+		lens[first..end].iter().sum::<usize>() + (end - first - 1)
+	};
+
+	// cost[lines][end]: best cost of putting words[..end] in lines lines
+	let mut cost = vec![vec![None; word_count + 1]; line_count + 1];
+	let mut from = vec![vec![0; word_count + 1]; line_count + 1];
+	cost[0][0] = Some(0i64);
+	for lines in 1..=line_count {
+		for end in lines..=word_count {
+			let mut best: Option<(i64, usize)> = None;
+			for first in lines - 1..end {
+				let previous = match cost[lines - 1][first] {
+					Some(previous) => previous,
+					None => continue
+				};
+				let len = line_len(first, end);
+				if len > max_chars {
+					continue;
+				}
+				let mut line_cost = previous + (len as i64 - target).abs();
+				if first > 0 {
+					line_cost += break_cost(words[first - 1], words[first]);
+				}
+				if best.is_none_or(|(best_cost, _)| line_cost < best_cost) {
+					best = Some((line_cost, first));
+				}
+			}
+			if let Some((best_cost, first)) = best {
+				cost[lines][end] = Some(best_cost);
+				from[lines][end] = first;
+			}
+		}
+	}
+	cost[line_count][word_count]?;
+	let mut breaks = Vec::new();
+	let mut end = word_count;
+	for lines in (2..=line_count).rev() {
+		end = from[lines][end];
+		breaks.push(end);
+	}
+	breaks.reverse();
+	Some(breaks)
+}
+
+// Name of an HTML like tag: "<font color=x>" gives "font", "</i>" gives "i"
+fn tag_name(tag: &str) -> &str {
+	let inner = tag.trim_start_matches('<').trim_start_matches('/').trim_end_matches('>');
+	inner.split(' ').next().unwrap_or("")
+}
+
+// Joins a span closed then opened again with the same tag, with only spaces
+// between: "</i> <i>", "</font><font color=\"red\">"...
+fn merge_spans(text: &str) -> String {
+	let mut ret = String::with_capacity(text.len());
+	let mut open_tags: Vec<&str> = Vec::new();
+	let mut rest = text;
+	while let Some(start) = rest.find('<') {
+		let end = match rest[start..].find('>') {
+			Some(len) => start + len + 1,
+			None => break
+		};
+		let tag = &rest[start..end];
+		ret.push_str(&rest[..start]);
+		rest = &rest[end..];
+		if tag.starts_with("</") {
+			if open_tags.last().is_some_and(|open| tag_name(open) == tag_name(tag)) {
+				let open = open_tags[open_tags.len() - 1];
+				let after = rest.trim_start();
+				if let Some(reopened) = after.strip_prefix(open) {
+					// The span goes on, the spaces stay
+					ret.push_str(&rest[..rest.len() - after.len()]);
+					rest = reopened;
+					continue;
+				}
+			}
+			if let Some(pos) = open_tags.iter().rposition(|open| tag_name(open) == tag_name(tag)) {
+				open_tags.remove(pos);
+			}
+		}
+		else if !tag.ends_with("/>") {
+			open_tags.push(tag);
+		}
+		ret.push_str(tag);
+	}
+	ret.push_str(rest);
+	// Inner spans may be next to each other now
+	if ret.len() < text.len() {
+		merge_spans(&ret)
+	}
+	else {
+		ret
+	}
+}
+
+#[test]
+fn test_merge_spans() {
+	assert_eq!(merge_spans("<i>a</i> <i>b</i>"), "<i>a b</i>");
+	assert_eq!(merge_spans("<i>a</i>  <i>b</i><i>c</i>"), "<i>a  bc</i>");
+	assert_eq!(merge_spans("<font color=\"red\">a</font> <font color=\"red\">b</font>"),
+		"<font color=\"red\">a b</font>");
+	// Other spans are kept
+	assert_eq!(merge_spans("<font color=\"red\">a</font> <font color=\"blue\">b</font>"),
+		"<font color=\"red\">a</font> <font color=\"blue\">b</font>");
+	assert_eq!(merge_spans("<i>a</i> b <i>c</i>"), "<i>a</i> b <i>c</i>");
+	assert_eq!(merge_spans("<i><b>a</b></i> <i><b>b</b></i>"), "<i><b>a b</b></i>");
+	assert_eq!(merge_spans("<i><b>a</b></i> <i>b</i>"), "<i><b>a</b> b</i>");
+}
+
+// Closes the tags still open at the end of a line and opens them again at
+// the start of the next one
+fn close_and_reopen(lines: Vec<String>) -> Vec<String> {
+	let mut open_tags: Vec<String> = Vec::new();
+	let mut ret = Vec::with_capacity(lines.len());
+	for line in lines {
+		let mut new_line: String = open_tags.concat();
+		new_line.push_str(&line);
+		let mut rest = &line[..];
+		while let Some(start) = rest.find('<') {
+			let end = match rest[start..].find('>') {
+				Some(len) => start + len + 1,
+				None => break
+			};
+			let tag = &rest[start..end];
+			if tag.starts_with("</") {
+				if let Some(pos) = open_tags.iter().rposition(|open| tag_name(open) == tag_name(tag)) {
+					open_tags.remove(pos);
+				}
+			}
+			else if !tag.ends_with("/>") {
+				open_tags.push(tag.to_string());
+			}
+			rest = &rest[end..];
+		}
+		for open in open_tags.iter().rev() {
+			new_line.push_str(&format!("</{}>", tag_name(open)));
+		}
+		ret.push(new_line);
+	}
+	ret
+}
+
+// Lines of a dialogue, one per speaker, are not joined
+fn is_dialogue(lines: &[String]) -> bool {
+	lines.len() >= 2 && lines.iter().all(|line| reading::strip_tags(line).trim_start().starts_with('-'))
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Joins the lines of a subtitle and breaks them again, in as few lines of
+// at most max_chars as possible. Returns false if the text does not fit in
+// max_lines lines, the subtitle is then left as is.
+pub fn wrap_subtitle(subtitle: &mut Subtitle, max_chars: usize, max_lines: usize) -> bool {
+	let lines: Vec<String> = subtitle.texts.clone();
+	if lines.is_empty() {
+		return true;
+	}
+	if is_dialogue(&lines) {
+		return lines.len() <= max_lines && lines.iter().all(|line| visible_len(line) <= max_chars);
+	}
+
+	// Spans closed and opened again at a line break
+	let text = merge_spans(&lines.join(" "));
+	let words = split_words(&text);
+	let lens: Vec<usize> = words.iter().map(|word| visible_len(word)).collect();
+
+	for line_count in 1..=max_lines.min(words.len()) {
+		if let Some(breaks) = best_breaks(&words, &lens, line_count, max_chars) {
+			let mut new_lines: Vec<String> = Vec::new();
+			let mut first = 0;
+			for &end in breaks.iter().chain([words.len()].iter()) {
+				new_lines.push(words[first..end].join(" "));
+				first = end;
+			}
+			subtitle.texts = close_and_reopen(new_lines);
+			return true;
+		}
+	}
+	false
+}
+
+#[test]
+fn test_wrap_subtitle() {
+	let wrap = |texts: &[&str], max_chars: usize| -> (bool, Vec<String>) {
+		let mut subtitle = Subtitle::default();
+		for text in texts.iter() {
+			subtitle.push_text(text);
+		}
+		let fits = wrap_subtitle(&mut subtitle, max_chars, 2);
+		(fits, subtitle.texts.clone())
+	};
+	assert_eq!(wrap(&["Short", "text"], 42), (true, vec!["Short text".to_string()]));
+	// After the punctuation, even if less balanced
+	assert_eq!(wrap(&["I told you already, you should have gone to the station"], 42).1,
+		vec!["I told you already,", "you should have gone to the station"]);
+	assert_eq!(wrap(&["We waited for hours at the station and nobody came"], 42).1,
+		vec!["We waited for hours at the station", "and nobody came"]);
+	// No break at a no-break space
+	assert_eq!(wrap(&["Tu veux venir avec nous ce soir\u{A0}?"], 30).1,
+		vec!["Tu veux venir", "avec nous ce soir\u{A0}?"]);
+	// Tags are closed and opened again
+	assert_eq!(wrap(&["<i>I never said that you were the one who took it</i>"], 30).1,
+		vec!["<i>I never said that you</i>", "<i>were the one who took it</i>"]);
+	assert_eq!(wrap(&["<font color=\"red\">Red and blue</font> text"], 12).1,
+		vec!["<font color=\"red\">Red and</font>", "<font color=\"red\">blue</font> text"]);
+	// Spans split over the lines are joined first
+	assert_eq!(wrap(&["<i>I never said</i>", "<i>that</i>  <i>you took it</i>"], 42).1,
+		vec!["<i>I never said that you took it</i>"]);
+	assert_eq!(wrap(&["<font color=\"red\">Red</font>", "<font color=\"red\">text</font>"], 42).1,
+		vec!["<font color=\"red\">Red text</font>"]);
+	// Dialogues are not joined
+	assert_eq!(wrap(&["- Yes?", "- No."], 42).1, vec!["- Yes?", "- No."]);
+	assert!(!wrap(&["This sentence cannot fit in two lines of twelve"], 12).0);
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Wraps all the subtitles. Returns the numbers of the subtitles that do not
+// fit.
+pub fn wrap_subtitles(subtitles: &mut [Subtitle], max_chars: usize, max_lines: usize) -> Vec<u32> {
+	let mut too_long = Vec::new();
+	for subtitle in subtitles.iter_mut() {
+		if !wrap_subtitle(subtitle, max_chars, max_lines) {
+			too_long.push(subtitle.num);
+		}
+	}
+	too_long
+}